@@ -0,0 +1,192 @@
+use colored::*;
+use std::path::{Path, PathBuf};
+
+/// The most bytes of files that can be attached to one query
+const MAX_ATTACHMENT_BYTES: usize = 256 * 1024;
+
+/// How many bytes at the start of a file are checked to detect binary files
+const BINARY_CHECK_BYTES: usize = 8 * 1024;
+
+/// Formats files as fenced code blocks labeled with their path, to be added to a prompt.
+/// Binary files and files past the size limit are skipped with a warning.
+///
+/// ### Arguments
+/// - `paths` - The paths of the files to attach
+pub fn format_files(paths: &[PathBuf]) -> anyhow::Result<String> {
+    let mut blocks: Vec<String> = Vec::new();
+    let mut total_bytes = 0;
+    for path in paths.iter() {
+        // Files past the limit are skipped without reading them
+        let size = std::fs::metadata(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {:?}: {}", path, e))?
+            .len();
+        if total_bytes as u64 + size > MAX_ATTACHMENT_BYTES as u64 {
+            skip_too_large(path);
+            continue;
+        }
+        let bytes =
+            std::fs::read(path).map_err(|e| anyhow::anyhow!("Failed to read {:?}: {}", path, e))?;
+        let check_len = bytes.len().min(BINARY_CHECK_BYTES);
+        let contents = match String::from_utf8(bytes) {
+            Ok(contents) if !contents.as_bytes()[..check_len].contains(&0) => contents,
+            _ => {
+                eprintln!(
+                    "{}",
+                    format!("Skipping {}: it looks like a binary file", path.display()).yellow()
+                );
+                continue;
+            }
+        };
+        // The file may have grown since its size was checked
+        if total_bytes + contents.len() > MAX_ATTACHMENT_BYTES {
+            skip_too_large(path);
+            continue;
+        }
+        total_bytes += contents.len();
+        blocks.push(code_block(path, &contents));
+    }
+    Ok(blocks.join("\n\n"))
+}
+
+/// Warns that a file is skipped because it would exceed the size limit
+fn skip_too_large(path: &Path) {
+    eprintln!(
+        "{}",
+        format!(
+            "Skipping {}: attached files would exceed the {} KB limit",
+            path.display(),
+            MAX_ATTACHMENT_BYTES / 1024
+        )
+        .yellow()
+    );
+}
+
+/// Wraps the contents of a file in a fenced code block labeled with its path
+fn code_block(path: &Path, contents: &str) -> String {
+    // The fence must be longer than any run of backticks in the file
+    let longest_run = contents
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    format!(
+        "File: {}\n{}{}\n{}\n{}",
+        path.display(),
+        fence,
+        language(path),
+        contents.trim_end_matches('\n'),
+        fence
+    )
+}
+
+/// Guesses the language of a file from its extension (or name) for the code block
+fn language(path: &Path) -> &'static str {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    match name {
+        "Dockerfile" => return "dockerfile",
+        "Makefile" => return "makefile",
+        _ => {}
+    }
+
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "ts" => "typescript",
+        "tsx" => "tsx",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "c" | "h" => "c",
+        "cpp" | "cc" | "cxx" | "hpp" => "cpp",
+        "cs" => "csharp",
+        "rb" => "ruby",
+        "php" => "php",
+        "lua" => "lua",
+        "sh" | "bash" => "bash",
+        "zsh" => "zsh",
+        "ps1" => "powershell",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "xml" => "xml",
+        "json" => "json",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "md" => "markdown",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_blocks_are_labeled_with_the_path_and_language() {
+        let block = code_block(Path::new("src/main.rs"), "fn main() {}\n");
+
+        assert_eq!(block, "File: src/main.rs\n```rust\nfn main() {}\n```");
+    }
+
+    #[test]
+    fn fence_is_longer_than_backticks_in_the_file() {
+        let contents = "Example:\n```sh\nls\n```\n";
+        let block = code_block(Path::new("README.md"), contents);
+
+        assert!(block.starts_with("File: README.md\n````markdown\n"));
+        assert!(block.ends_with("\n```\n````"));
+
+        let block = code_block(Path::new("notes.txt"), "five ````` backticks");
+        assert!(block.starts_with("File: notes.txt\n``````\n"));
+        assert!(block.ends_with("backticks\n``````"));
+    }
+
+    #[test]
+    fn language_is_guessed_from_the_extension() {
+        let cases = [
+            ("lib.rs", "rust"),
+            ("script.PY", "python"),
+            ("index.mjs", "javascript"),
+            ("app.tsx", "tsx"),
+            ("main.hpp", "cpp"),
+            ("config.yml", "yaml"),
+            ("dir/Dockerfile", "dockerfile"),
+            ("Makefile", "makefile"),
+            ("notes.txt", ""),
+            ("LICENSE", ""),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(language(Path::new(path)), expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn binary_files_are_skipped() {
+        let dir = std::env::temp_dir().join(format!("pgpt-attachment-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let text = dir.join("hello.py");
+        let binary = dir.join("image.bin");
+        std::fs::write(&text, "print('hi')\n").unwrap();
+        std::fs::write(&binary, [0x89, b'P', b'N', b'G', 0, 0, 1]).unwrap();
+        let formatted = format_files(&[text.clone(), binary]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            formatted.unwrap(),
+            format!("File: {}\n```python\nprint('hi')\n```", text.display())
+        );
+    }
+}
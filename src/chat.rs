@@ -0,0 +1,483 @@
+use crate::config::model::Model;
+use crate::config::session::Session;
+use crate::config::{utils, CacheValue, Config, QueryArgs};
+use crate::gpt::{GPTRole, GPTUsage};
+use crate::provider::{self, ChatProvider, ChatRequest, SamplingParams};
+use crate::{check_capabilities, check_params, create_skin, render_response, QuerySettings};
+use colored::*;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::str::FromStr;
+use std::sync::Arc;
+use termimad::MadSkin;
+
+/// Starts and ends a message that spans several lines
+const MULTI_LINE_DELIMITER: &str = "\"\"\"";
+
+/// A conversation that keeps its provider and messages between turns
+struct Chat {
+    config: Arc<Config>,
+    base_url: Option<String>,
+    model: Model,
+    params: SamplingParams,
+    system_prompt: Option<String>,
+    provider: Box<dyn ChatProvider>,
+    /// The session every prompt/response pair is saved to
+    session: Session,
+    role: Option<String>,
+    /// The prompt/response pairs of the conversation, starting with the context from the session
+    messages: Vec<CacheValue>,
+    /// How many of the most recent pairs are sent with each message, all of them when `None`
+    context: Option<usize>,
+    stream: bool,
+    skin: MadSkin,
+    usage: GPTUsage,
+    cost: f64,
+    /// Whether a reply came from a model without a price
+    unknown_cost: bool,
+}
+
+impl Chat {
+    /// Sends a message with the conversation so far, renders the reply and saves the pair to the
+    /// session
+    ///
+    /// ### Arguments
+    /// - `prompt` - The message to send
+    fn send(&mut self, prompt: &str) -> anyhow::Result<()> {
+        let request = self.request(prompt)?;
+        let capabilities = self.model.capabilities();
+        check_capabilities(&self.model, &capabilities, &request)?;
+
+        let mut header_printed = false;
+        let response = render_response(
+            self.provider.as_ref(),
+            &request,
+            &self.model,
+            &self.skin,
+            self.stream && capabilities.streaming != Some(false),
+            &mut header_printed,
+        )?;
+        self.usage += &response.usage;
+        match response.usage.total_cost(&self.model, &self.config.pricing) {
+            Some(cost) => self.cost += cost,
+            None => self.unknown_cost = true,
+        }
+
+        let cache_value = CacheValue {
+            prompt: prompt.to_string(),
+            response: response.text,
+            images: Vec::new(),
+            role: self.role.clone(),
+        };
+        self.messages.push(cache_value.clone());
+        let mut cache = utils::load_cache(&self.session.name)?;
+        cache.push(cache_value);
+        let start = cache.len().saturating_sub(self.session.cache_length);
+        utils::save_cache(&self.session.name, cache.split_off(start))?;
+        Ok(())
+    }
+
+    /// Creates the request for a message, with the system prompt and the previous prompt/response
+    /// pairs that are sent as context
+    ///
+    /// ### Arguments
+    /// - `prompt` - The message to send
+    fn request(&self, prompt: &str) -> anyhow::Result<ChatRequest> {
+        let mut request_builder = ChatRequest::builder();
+        request_builder.model(&self.model).params(&self.params);
+        if let Some(system_prompt) = &self.system_prompt {
+            request_builder.message(GPTRole::System, system_prompt);
+        }
+        let start = match self.context {
+            Some(context) => self.messages.len().saturating_sub(context),
+            None => 0,
+        };
+        for message in self.messages[start..].iter() {
+            request_builder.message(GPTRole::User, &message.prompt);
+            request_builder.message(GPTRole::Assistant, &message.response);
+        }
+        request_builder.message(GPTRole::User, prompt);
+        request_builder.build()
+    }
+
+    /// Runs a slash command
+    ///
+    /// ### Arguments
+    /// - `command` - The command, e.g. `/model`
+    /// - `argument` - The rest of the line after the command
+    fn run_command(&mut self, command: &str, argument: &str) -> anyhow::Result<()> {
+        match (command, argument) {
+            ("/help", _) => print_help(),
+            ("/model", "") => println!("{}: {}", "Model".cyan(), self.model),
+            ("/model", model) => {
+                let model = Model::from_str(model)?;
+                check_params(&self.params, model.provider())?;
+                self.provider =
+                    provider::create_provider(&model, &self.config, self.base_url.as_deref())?;
+                println!("Switching to {}", model.to_string().cyan());
+                self.model = model;
+            }
+            ("/context", "") => match self.context {
+                Some(context) => println!("{}: {}", "Context".cyan(), context),
+                None => println!(
+                    "{}: all ({} prompt/response pairs)",
+                    "Context".cyan(),
+                    self.messages.len()
+                ),
+            },
+            ("/context", "all") => {
+                println!("Sending {} previous messages", "all".cyan());
+                self.context = None;
+            }
+            ("/context", context) => {
+                let context: usize = context.parse().map_err(|_| {
+                    anyhow::anyhow!("Expected a number or `all`, got `{}`", context)
+                })?;
+                println!(
+                    "Sending the last {} prompt/response pairs",
+                    context.to_string().cyan()
+                );
+                self.context = Some(context);
+            }
+            ("/clear", _) => {
+                self.messages.clear();
+                println!(
+                    "Cleared the conversation, the session history is kept (clear it with {})",
+                    format!("pgpt config clear cache --session {}", self.session.name).cyan()
+                );
+            }
+            ("/cost", _) => {
+                println!(
+                    "{}: {} prompt, {} completion",
+                    "Tokens".cyan(),
+                    self.usage.prompt_tokens,
+                    self.usage.completion_tokens
+                );
+                let unknown = if self.unknown_cost {
+                    " (not counting replies from models without a price)"
+                } else {
+                    ""
+                };
+                println!("{}: ${:.6}{}", "Cost".green(), self.cost, unknown);
+            }
+            ("/save", "") => return Err(anyhow::anyhow!("Usage: /save <PATH>")),
+            ("/save", path) => {
+                std::fs::write(path, self.transcript())
+                    .map_err(|e| anyhow::anyhow!("Failed to save to {:?}: {}", path, e))?;
+                println!("Saved the conversation to {}", path.cyan());
+            }
+            ("/system", "") => println!(
+                "{}: {}",
+                "System Prompt".cyan(),
+                self.system_prompt.as_deref().unwrap_or("Not set")
+            ),
+            ("/system", "clear") => {
+                println!("Cleared {}", "system-prompt".cyan());
+                self.system_prompt = None;
+            }
+            ("/system", system_prompt) => {
+                println!(
+                    "Setting {} to {}",
+                    "system-prompt".cyan(),
+                    system_prompt.cyan()
+                );
+                self.system_prompt = Some(system_prompt.to_string());
+            }
+            (command, _) => return Err(anyhow::anyhow!("Unknown command {}, see /help", command)),
+        }
+        Ok(())
+    }
+
+    /// The conversation as Markdown
+    fn transcript(&self) -> String {
+        let mut transcript = String::new();
+        if let Some(system_prompt) = &self.system_prompt {
+            transcript.push_str(&format!("## System\n\n{}\n\n", system_prompt));
+        }
+        for message in self.messages.iter() {
+            transcript.push_str(&format!(
+                "## You\n\n{}\n\n## GPT\n\n{}\n\n",
+                message.prompt, message.response
+            ));
+        }
+        transcript
+    }
+}
+
+fn print_help() {
+    let commands = [
+        ("/model [MODEL]", "Show the model, or switch to another one"),
+        (
+            "/context [N|all]",
+            "Show or set how many previous prompt/response pairs are sent",
+        ),
+        ("/clear", "Start the conversation over"),
+        ("/cost", "Show the tokens used and the cost of the chat"),
+        ("/save <PATH>", "Save the conversation as Markdown"),
+        (
+            "/system [PROMPT|clear]",
+            "Show, set or clear the system prompt",
+        ),
+        ("/help", "Show this help"),
+        ("/exit", "End the chat (or press Ctrl-D)"),
+    ];
+    for (command, description) in commands {
+        println!("{:<24}{}", command.cyan(), description);
+    }
+    println!(
+        "Start and end a message with {} to write it over several lines, or end a line with {} to continue it",
+        MULTI_LINE_DELIMITER.cyan(),
+        "\\".cyan()
+    );
+}
+
+/// Reads a message, which can span several lines. Returns `None` at the end of input.
+///
+/// ### Arguments
+/// - `editor` - The line editor to read with
+fn read_message(editor: &mut DefaultEditor) -> anyhow::Result<Option<String>> {
+    let mut lines: Vec<String> = Vec::new();
+    loop {
+        let prompt = if lines.is_empty() { "pgpt> " } else { "...   " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C discards the message being written
+            Err(ReadlineError::Interrupted) => {
+                lines.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) if lines.is_empty() => return Ok(None),
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        let in_block = lines
+            .first()
+            .is_some_and(|first| first.starts_with(MULTI_LINE_DELIMITER));
+        if in_block {
+            if let Some(last) = line.strip_suffix(MULTI_LINE_DELIMITER) {
+                lines.push(last.to_string());
+                break;
+            }
+            lines.push(line);
+        } else if lines.is_empty() && line.trim_start().starts_with(MULTI_LINE_DELIMITER) {
+            let first = line.trim_start();
+            // A block opened and closed on the same line
+            if first.len() > 2 * MULTI_LINE_DELIMITER.len() && first.ends_with(MULTI_LINE_DELIMITER)
+            {
+                lines.push(first.to_string());
+                break;
+            }
+            lines.push(first.to_string());
+        } else if let Some(continued) = line.strip_suffix('\\') {
+            lines.push(continued.to_string());
+        } else {
+            lines.push(line);
+            break;
+        }
+    }
+
+    let message = lines.join("\n");
+    let message = message
+        .strip_prefix(MULTI_LINE_DELIMITER)
+        .map(|message| {
+            message
+                .strip_suffix(MULTI_LINE_DELIMITER)
+                .unwrap_or(message)
+        })
+        .unwrap_or(&message);
+    Ok(Some(message.trim().to_string()))
+}
+
+/// Handles logic for the chat command
+///
+/// ### Arguments
+/// - `args` - An Arc value for the arguments from the CLI related to the chat
+/// - `config` - An Arc value for the config
+pub fn run_chat(args: Arc<QueryArgs>, config: Arc<Config>) -> anyhow::Result<()> {
+    let QuerySettings {
+        model,
+        params,
+        system_prompt,
+    } = QuerySettings::resolve(&args, &config)?;
+    let provider = provider::create_provider(&model, &config, args.base_url.as_deref())?;
+
+    let session = config.session(args.session.as_deref())?;
+    let cache = utils::load_cache(&session.name)?;
+    let start = cache
+        .len()
+        .saturating_sub(args.context.unwrap_or(session.context));
+    let messages = Vec::from(&cache[start..]);
+
+    let mut chat = Chat {
+        config: config.clone(),
+        base_url: args.base_url.clone(),
+        model,
+        params,
+        system_prompt,
+        provider,
+        session,
+        role: args.role.clone(),
+        messages,
+        context: args.context,
+        stream: !args.no_stream,
+        skin: create_skin(),
+        usage: GPTUsage::default(),
+        cost: 0.0,
+        unknown_cost: false,
+    };
+
+    let mut editor = DefaultEditor::new()?;
+    let history_path = utils::chat_history_file_path();
+    if history_path.exists() {
+        if let Err(e) = editor.load_history(&history_path) {
+            eprintln!(
+                "{}",
+                format!("Failed to load the input history: {}", e).yellow()
+            );
+        }
+    }
+
+    println!(
+        "{}",
+        format!(
+            "Chatting with {} in session {} ({} previous messages). Type /help for commands, Ctrl-D to exit.",
+            chat.model.to_string().magenta(),
+            chat.session.name.magenta(),
+            chat.messages.len()
+        )
+        .cyan()
+    );
+    while let Some(message) = read_message(&mut editor)? {
+        if message.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(message.as_str())?;
+        if let Err(e) = editor.save_history(&history_path) {
+            eprintln!(
+                "{}",
+                format!("Failed to save the input history: {}", e).yellow()
+            );
+        }
+
+        if message == "/exit" || message == "/quit" {
+            break;
+        }
+        let result = if message.starts_with('/') {
+            let (command, argument) = message
+                .split_once(char::is_whitespace)
+                .unwrap_or((&message, ""));
+            chat.run_command(command, argument.trim())
+        } else {
+            chat.send(&message)
+        };
+        // A failed message or command doesn't end the chat
+        if let Err(e) = result {
+            eprintln!("{} {:?}", "Error:".red(), e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::pricing::PricingTable;
+    use crate::config::session::DEFAULT_SESSION;
+    use crate::provider::ollama::OllamaClient;
+    use crate::provider::retry::RetryPolicy;
+
+    /// A chat with the given previous prompt/response pairs and context
+    fn chat(messages: &[(&str, &str)], context: Option<usize>) -> Chat {
+        let config = Config {
+            model: Model::GPT4o,
+            cache_length: 5,
+            context: 0,
+            base_url: String::new(),
+            azure: None,
+            retry_policy: RetryPolicy::default(),
+            sampling: SamplingParams::default(),
+            pricing: PricingTable::defaults(),
+            system_prompt: None,
+            current_session: DEFAULT_SESSION.to_string(),
+            sessions: Default::default(),
+        };
+        Chat {
+            config: Arc::new(config),
+            base_url: None,
+            model: Model::GPT4o,
+            params: SamplingParams::default(),
+            system_prompt: Some(String::from("Be brief.")),
+            // Never sent to, building a request doesn't need a server
+            provider: Box::new(OllamaClient::new("http://127.0.0.1:1").unwrap()),
+            session: Session::new(DEFAULT_SESSION, None, 5, 0).unwrap(),
+            role: None,
+            messages: messages
+                .iter()
+                .map(|(prompt, response)| CacheValue {
+                    prompt: prompt.to_string(),
+                    response: response.to_string(),
+                    images: Vec::new(),
+                    role: None,
+                })
+                .collect(),
+            context,
+            stream: false,
+            skin: create_skin(),
+            usage: GPTUsage::default(),
+            cost: 0.0,
+            unknown_cost: false,
+        }
+    }
+
+    /// The role and text of each message in the request for a prompt
+    fn sent(chat: &Chat, prompt: &str) -> Vec<(String, String)> {
+        chat.request(prompt)
+            .unwrap()
+            .messages
+            .iter()
+            .map(|message| {
+                let role = serde_json::to_value(&message.role).unwrap();
+                (role.as_str().unwrap().to_string(), message.content.text())
+            })
+            .collect()
+    }
+
+    fn pair(role: &str, text: &str) -> (String, String) {
+        (role.to_string(), text.to_string())
+    }
+
+    #[test]
+    fn second_turn_is_sent_with_the_first() {
+        let chat = chat(&[("What is 2 + 2?", "4")], None);
+
+        assert_eq!(
+            sent(&chat, "And times 3?"),
+            [
+                pair("system", "Be brief."),
+                pair("user", "What is 2 + 2?"),
+                pair("assistant", "4"),
+                pair("user", "And times 3?"),
+            ]
+        );
+    }
+
+    #[test]
+    fn context_limits_the_previous_turns() {
+        let turns = [("one", "1"), ("two", "2"), ("three", "3")];
+
+        assert_eq!(
+            sent(&chat(&turns, Some(1)), "four"),
+            [
+                pair("system", "Be brief."),
+                pair("user", "three"),
+                pair("assistant", "3"),
+                pair("user", "four"),
+            ]
+        );
+        assert_eq!(
+            sent(&chat(&turns, Some(0)), "four"),
+            [pair("system", "Be brief."), pair("user", "four")]
+        );
+    }
+}
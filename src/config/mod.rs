@@ -1,10 +1,26 @@
 pub mod model;
+pub mod pricing;
+pub mod role;
+pub mod session;
+pub mod template;
 pub mod utils;
 
-use crate::config::model::Model;
+use crate::config::model::{DiscoveredModels, Model, Provider};
+use crate::config::pricing::PricingTable;
+use crate::config::role::{Role, Roles};
+use crate::config::session::{Session, SessionSettings, DEFAULT_SESSION};
+use crate::config::template::Template;
 use crate::encryption::{encrypt, nonce};
-use clap::Parser;
+use crate::error::PgptError;
+use crate::provider::retry::RetryPolicy;
+use crate::provider::{self, SamplingParams};
+use crate::render::LogprobsDisplay;
+use anyhow::Context;
+use clap::{Parser, ValueEnum};
 use colored::*;
+use std::error::Error;
+use std::io::{IsTerminal, Read};
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -20,6 +36,8 @@ pub struct CLI {
     command: Commands,
 }
 
+// The commands are parsed once, so the size of the `Query` variant does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(clap::Subcommand, Debug)]
 pub enum Commands {
     /// Make a query to ChatGPT
@@ -32,7 +50,15 @@ pub enum Commands {
         #[arg(long)]
         cost: bool,
 
-        /// Use a specific model for the query (optional).
+        /// Use this system prompt instead of the configured one. An empty prompt sends none (optional)
+        #[arg(long, value_name = "PROMPT")]
+        system: Option<String>,
+
+        /// Use a role added with `pgpt role add`: its system prompt, model and sampling defaults (optional)
+        #[arg(long, short, value_name = "NAME")]
+        role: Option<String>,
+
+        /// Use a specific model for the query, by alias or by an ID listed by `pgpt models list` (optional).
         #[arg(long, short, value_enum)]
         model: Option<Model>,
 
@@ -40,15 +66,585 @@ pub enum Commands {
         #[arg(long, short)]
         context: Option<usize>,
 
+        /// Use the history of this session instead of the current one, see `pgpt session list` (optional)
+        #[arg(long, value_name = "NAME")]
+        session: Option<String>,
+
         /// Display the context that is being passed with the query
         #[arg(long, short)]
         show_context: bool,
+
+        /// Wait for the full response instead of streaming it as it is generated
+        #[arg(long)]
+        no_stream: bool,
+
+        /// Use a specific API base URL for the query, e.g. `http://localhost:8080/v1` (optional)
+        #[arg(long)]
+        base_url: Option<String>,
+
+        /// Let the model call the built-in tools (current time, reading files and listing directories in the working directory)
+        #[arg(long)]
+        tools: bool,
+
+        /// Attach an image (PNG, JPEG, GIF or WebP) to the query. Can be repeated. Requires a model that supports images. (optional)
+        #[arg(long = "image", value_name = "PATH")]
+        images: Vec<PathBuf>,
+
+        /// Attach a text file to the query as a code block. Can be repeated. (optional)
+        #[arg(long = "file", value_name = "PATH")]
+        files: Vec<PathBuf>,
+
+        /// [0-2] Higher values make the response more random (optional)
+        #[arg(long)]
+        temperature: Option<f32>,
+
+        /// [0-1] Only sample from the tokens that make up this probability mass (optional)
+        #[arg(long)]
+        top_p: Option<f32>,
+
+        /// The maximum number of tokens to generate (optional)
+        #[arg(long)]
+        max_tokens: Option<u32>,
+
+        /// Sample deterministically (as far as the provider allows) with this seed (optional)
+        #[arg(long, allow_negative_numbers = true)]
+        seed: Option<i64>,
+
+        /// Stop generating when this sequence is produced. Can be repeated up to 4 times. (optional)
+        #[arg(long = "stop", value_name = "SEQUENCE")]
+        stop: Vec<String>,
+
+        /// [-2-2] Positive values make the model more likely to talk about new topics (optional)
+        #[arg(long, allow_negative_numbers = true)]
+        presence_penalty: Option<f32>,
+
+        /// [-2-2] Positive values make the model less likely to repeat itself (optional)
+        #[arg(long, allow_negative_numbers = true)]
+        frequency_penalty: Option<f32>,
+
+        /// Ask for a JSON object and print the raw JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Ask for JSON matching the JSON Schema in this file, check the response against it and print the raw JSON (optional)
+        #[arg(long, value_name = "PATH", conflicts_with = "json")]
+        json_schema: Option<PathBuf>,
+
+        /// [1-10] Generate this many choices and pick the one to save to the cache
+        #[arg(
+            short = 'n',
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u32).range(1..=10),
+            conflicts_with_all = ["tools", "json", "json_schema"]
+        )]
+        choices: u32,
+
+        /// The choice to save to the cache when generating several, instead of being asked (optional)
+        #[arg(long, value_name = "N")]
+        pick: Option<u32>,
+
+        /// Show how likely each generated token was, as colored text or as a table (`--logprobs=table`)
+        #[arg(
+            long,
+            value_enum,
+            value_name = "DISPLAY",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "color",
+            conflicts_with = "choices"
+        )]
+        logprobs: Option<LogprobsDisplay>,
+
+        /// [0-20] Also show this many of the most likely tokens at each position. Implies `--logprobs=table` (optional)
+        #[arg(
+            long,
+            value_name = "N",
+            value_parser = clap::value_parser!(u32).range(0..=20),
+            conflicts_with = "choices"
+        )]
+        top_logprobs: Option<u32>,
+    },
+    /// Chat with ChatGPT in an interactive session, with slash commands (see `/help`)
+    Chat {
+        /// Use a specific model for the chat, switch with `/model` (optional)
+        #[arg(long, short, value_enum)]
+        model: Option<Model>,
+
+        /// Use a role added with `pgpt role add` (optional)
+        #[arg(long, short, value_name = "NAME")]
+        role: Option<String>,
+
+        /// Use this system prompt instead of the configured one, change it with `/system` (optional)
+        #[arg(long, value_name = "PROMPT")]
+        system: Option<String>,
+
+        /// Continue this session instead of the current one (optional)
+        #[arg(long, value_name = "NAME")]
+        session: Option<String>,
+
+        /// The number of previous prompt/response pairs to continue from and send with each message, all of the chat by default. Change it with `/context` (optional)
+        #[arg(long, short)]
+        context: Option<usize>,
+
+        /// Wait for full responses instead of streaming them as they are generated
+        #[arg(long)]
+        no_stream: bool,
+
+        /// Use a specific API base URL for the chat (optional)
+        #[arg(long)]
+        base_url: Option<String>,
     },
     /// Configure settings for using the CLI
     Config {
         #[command(subcommand)]
         config_commands: ConfigCommands,
     },
+    /// Run, list and validate prompt templates with `{{name}}` placeholders
+    Template {
+        #[command(subcommand)]
+        template_commands: TemplateCommands,
+    },
+    /// Discover the models offered by a provider
+    Models {
+        #[command(subcommand)]
+        models_commands: ModelsCommands,
+    },
+    /// Show the model prices used to calculate costs
+    Pricing {
+        #[command(subcommand)]
+        pricing_commands: PricingCommands,
+    },
+    /// Manage sessions: named conversations, each with its own history
+    Session {
+        #[command(subcommand)]
+        session_commands: SessionCommands,
+    },
+    /// Manage named roles: system prompts with their own model and sampling defaults
+    Role {
+        #[command(subcommand)]
+        role_commands: RoleCommands,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum TemplateCommands {
+    /// Fill in a template and query ChatGPT with it
+    Run {
+        /// The name of the template (its file name without the extension) or the path to a template file
+        name: String,
+
+        /// Set a variable of the template. A value of `@<PATH>` inserts the contents of the file. Can be repeated.
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = template::parse_var)]
+        vars: Vec<(String, String)>,
+
+        /// Display the total cost associated with prompt/response
+        #[arg(long)]
+        cost: bool,
+
+        /// Use this system prompt instead of the configured one. An empty prompt sends none (optional)
+        #[arg(long, value_name = "PROMPT")]
+        system: Option<String>,
+
+        /// Use a role added with `pgpt role add` (optional)
+        #[arg(long, short, value_name = "NAME")]
+        role: Option<String>,
+
+        /// Use a specific model for the query (optional)
+        #[arg(long, short, value_enum)]
+        model: Option<Model>,
+
+        /// The number of previous prompt/response pairs to include in the query (optional)
+        #[arg(long, short)]
+        context: Option<usize>,
+
+        /// Use the history of this session instead of the current one (optional)
+        #[arg(long, value_name = "NAME")]
+        session: Option<String>,
+
+        /// Wait for the full response instead of streaming it as it is generated
+        #[arg(long)]
+        no_stream: bool,
+
+        /// Use a specific API base URL for the query (optional)
+        #[arg(long)]
+        base_url: Option<String>,
+    },
+    /// List the templates and their variables
+    List,
+    /// Check that templates can be filled in, all of them if no name is given
+    Validate {
+        /// The name of the template or the path to a template file (optional)
+        name: Option<String>,
+    },
+}
+
+impl TemplateCommands {
+    pub fn run(&self) -> anyhow::Result<()> {
+        match self {
+            Self::Run {
+                name,
+                vars,
+                cost,
+                system,
+                role,
+                model,
+                context,
+                session,
+                no_stream,
+                base_url,
+            } => {
+                let query = Template::load(name)?.render(&vars.iter().cloned().collect())?;
+                let args = QueryArgs {
+                    query,
+                    system: system.clone(),
+                    role: role.clone(),
+                    model: model.clone(),
+                    cost: *cost,
+                    context: *context,
+                    session: session.clone(),
+                    no_stream: *no_stream,
+                    base_url: base_url.clone(),
+                    ..QueryArgs::default()
+                };
+                // Filled in templates are sent like any other query
+                let config = Config::load_config().context("Failed to load config.")?;
+                crate::run_query(Arc::new(args), config)?;
+            }
+            Self::List => {
+                let templates = Template::load_all()?;
+                if templates.is_empty() {
+                    println!(
+                        "No templates found, add them as files in {}",
+                        format!("{:?}", utils::templates_dir_path()).cyan()
+                    );
+                }
+                for template in templates.iter() {
+                    template.show();
+                }
+            }
+            Self::Validate { name: Some(name) } => {
+                let template = Template::load(name)?;
+                template.variables()?;
+                println!("{} {}", template.name.cyan(), "is valid".green());
+            }
+            Self::Validate { name: None } => {
+                let templates = Template::load_all()?;
+                let mut invalid = 0;
+                for template in templates.iter() {
+                    match template.variables() {
+                        Ok(_) => println!("{} {}", template.name.cyan(), "is valid".green()),
+                        Err(e) => {
+                            invalid += 1;
+                            println!("{}", e.to_string().red());
+                        }
+                    }
+                }
+                if invalid > 0 {
+                    return Err(PgptError::InvalidArgument(format!(
+                        "{} of {} templates are invalid",
+                        invalid,
+                        templates.len()
+                    ))
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum SessionCommands {
+    /// List the sessions with the size of their history and their settings
+    List,
+    /// Make a session the current one, creating it if it doesn't exist
+    Switch { name: String },
+    /// Rename a session
+    Rename { name: String, new_name: String },
+    /// Remove a session and its history
+    Rm { name: String },
+}
+
+impl SessionCommands {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let mut config = utils::load_or_register_config_file()?;
+        let names = session::names(&config)?;
+        let current = session::current_name(&config).to_string();
+        match self {
+            Self::List => {
+                for name in names.iter() {
+                    let session = config.session(name)?;
+                    let marker = if *name == current { "*" } else { " " };
+                    println!(
+                        "{} {} ({} cached, cache-length {}, context {})",
+                        marker.green(),
+                        name.cyan(),
+                        utils::load_cache(name)?.len(),
+                        session.cache_length,
+                        session.context
+                    );
+                }
+                return Ok(());
+            }
+            Self::Switch { name } => {
+                utils::validate_name("Session", name)?;
+                if names.contains(name) {
+                    println!("Switching to session {}", name.cyan());
+                } else {
+                    println!("Switching to new session {}", name.cyan());
+                }
+                config.session = (name != DEFAULT_SESSION).then(|| name.clone());
+            }
+            Self::Rename { name, new_name } => {
+                utils::validate_name("Session", new_name)?;
+                if !names.contains(name) {
+                    return Err(session::not_found(name).into());
+                }
+                if names.contains(new_name) {
+                    return Err(PgptError::InvalidArgument(format!(
+                        "Session `{}` already exists",
+                        new_name
+                    ))
+                    .into());
+                }
+                let cache_path = utils::cache_file_path(name);
+                if cache_path.exists() {
+                    let new_cache_path = utils::cache_file_path(new_name);
+                    std::fs::create_dir_all(new_cache_path.parent().unwrap())?;
+                    std::fs::rename(&cache_path, &new_cache_path)?;
+                }
+                if let Some(settings) = config.sessions.remove(name) {
+                    config.sessions.insert(new_name.clone(), settings);
+                }
+                if current == *name {
+                    config.session = (new_name != DEFAULT_SESSION).then(|| new_name.clone());
+                }
+                println!("Renaming session {} to {}", name.cyan(), new_name.cyan());
+            }
+            Self::Rm { name } => {
+                if !names.contains(name) {
+                    return Err(session::not_found(name).into());
+                }
+                if name == DEFAULT_SESSION {
+                    // The default session always exists, so only its history is removed
+                    utils::save_cache(name, Vec::new())?;
+                } else if utils::cache_file_path(name).exists() {
+                    std::fs::remove_file(utils::cache_file_path(name))?;
+                }
+                config.sessions.remove(name);
+                println!("Removing session {}", name.cyan());
+                if current == *name {
+                    config.session = None;
+                    println!("Switching to session {}", DEFAULT_SESSION.cyan());
+                }
+            }
+        }
+        utils::save_config_file(&config)?;
+        Ok(())
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum RoleCommands {
+    /// Add a role, replacing any role with the same name
+    Add {
+        /// The name to use the role by, e.g. `rust-review`
+        name: String,
+        /// The system prompt of the role
+        #[arg(required = true)]
+        system_prompt: Vec<String>,
+        /// Always use this model with the role (optional)
+        #[arg(long, short, value_enum)]
+        model: Option<Model>,
+        /// [0-2] The sampling temperature of the role (optional)
+        #[arg(long)]
+        temperature: Option<f32>,
+        /// [0-1] The nucleus sampling probability mass of the role (optional)
+        #[arg(long)]
+        top_p: Option<f32>,
+        /// The maximum number of tokens to generate with the role (optional)
+        #[arg(long)]
+        max_tokens: Option<u32>,
+        /// The seed for deterministic sampling with the role (optional)
+        #[arg(long, allow_negative_numbers = true)]
+        seed: Option<i64>,
+        /// The stop sequences of the role. Can be repeated up to 4 times. (optional)
+        #[arg(long = "stop", value_name = "SEQUENCE")]
+        stop: Vec<String>,
+        /// [-2-2] The presence penalty of the role (optional)
+        #[arg(long, allow_negative_numbers = true)]
+        presence_penalty: Option<f32>,
+        /// [-2-2] The frequency penalty of the role (optional)
+        #[arg(long, allow_negative_numbers = true)]
+        frequency_penalty: Option<f32>,
+    },
+    /// List the roles
+    List,
+    /// Show the system prompt, model and sampling defaults of a role
+    Show { name: String },
+    /// Remove a role
+    Rm { name: String },
+}
+
+impl RoleCommands {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let mut roles = Roles::load()?;
+        match self {
+            Self::Add {
+                name,
+                system_prompt,
+                model,
+                temperature,
+                top_p,
+                max_tokens,
+                seed,
+                stop,
+                presence_penalty,
+                frequency_penalty,
+            } => {
+                let system_prompt = system_prompt.join(" ").trim().to_string();
+                if system_prompt.is_empty() {
+                    return Err(anyhow::anyhow!("Received empty system prompt"));
+                }
+                let sampling = SamplingParams {
+                    temperature: *temperature,
+                    top_p: *top_p,
+                    max_tokens: *max_tokens,
+                    seed: *seed,
+                    stop: if stop.is_empty() {
+                        None
+                    } else {
+                        Some(stop.clone())
+                    },
+                    presence_penalty: *presence_penalty,
+                    frequency_penalty: *frequency_penalty,
+                };
+                let provider = match model {
+                    Some(model) => model.provider(),
+                    None => utils::load_or_register_config_file()
+                        .ok()
+                        .and_then(|config| Model::from_str(&config.model).ok())
+                        .map(|model| model.provider())
+                        .unwrap_or_default(),
+                };
+                sampling.validate(provider)?;
+
+                let role = Role {
+                    system_prompt,
+                    model: model.as_ref().map(Model::to_string),
+                    sampling,
+                };
+                if roles.insert(name, role)? {
+                    println!("Replacing role {}", name.cyan());
+                } else {
+                    println!("Adding role {}", name.cyan());
+                }
+                roles.save()?;
+                println!(
+                    "Use it with {}",
+                    format!("pgpt query --role {} <QUERY>", name).cyan()
+                );
+            }
+            Self::List => roles.list(),
+            Self::Show { name } => roles.get(name)?.show(name),
+            Self::Rm { name } => {
+                roles.remove(name)?;
+                roles.save()?;
+                println!("Removed role {}", name.cyan());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum PricingCommands {
+    /// Show the price of every model, including the overrides in `pricing.json`
+    Show,
+}
+
+impl PricingCommands {
+    pub fn run(&self) -> anyhow::Result<()> {
+        match self {
+            Self::Show => PricingTable::load()?.show(),
+        }
+        Ok(())
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ModelsCommands {
+    /// Fetch the models a provider offers and save them so their IDs can be used as models
+    List {
+        /// The provider to list the models of (defaults to the provider of the configured model)
+        #[arg(long, value_enum)]
+        provider: Option<Provider>,
+        /// Show the saved models instead of fetching them again
+        #[arg(long)]
+        cached: bool,
+        /// Use a specific API base URL (optional)
+        #[arg(long)]
+        base_url: Option<String>,
+    },
+}
+
+impl ModelsCommands {
+    pub fn run(&self, config: &Config) -> anyhow::Result<()> {
+        match self {
+            Self::List {
+                provider,
+                cached,
+                base_url,
+            } => {
+                let provider = provider.unwrap_or_else(|| config.model.provider());
+                // OpenAI models are sent to the deployments, whatever the OpenAI API offers
+                if let Some(azure) = config.azure.as_ref().filter(|azure| azure.is_enabled()) {
+                    if matches!(provider, Provider::OpenAI | Provider::Azure) {
+                        return azure.list_deployments();
+                    }
+                }
+                let mut models_cache = utils::load_models_cache()?;
+                let discovered = if *cached {
+                    models_cache.get(provider).cloned().ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "No {} models have been saved yet, run `pgpt models list --provider {}`",
+                            provider,
+                            provider.id()
+                        )
+                    })?
+                } else {
+                    let client = provider::create_client(provider, config, base_url.as_deref())?;
+                    let mut models = client.list_models()?;
+                    models.sort();
+                    let discovered = DiscoveredModels::new(models);
+                    models_cache.insert(provider, discovered.clone());
+                    utils::save_models_cache(&models_cache)?;
+                    discovered
+                };
+
+                println!(
+                    "{}",
+                    format!(
+                        "{} models (fetched {})",
+                        provider.to_string().magenta(),
+                        discovered.age()
+                    )
+                    .cyan()
+                );
+                for id in discovered.models.iter() {
+                    println!("{}", id);
+                }
+                println!();
+                println!(
+                    "Use any of them with {} or {}",
+                    "pgpt query --model <ID>".cyan(),
+                    "pgpt config set model <ID>".cyan()
+                );
+                Ok(())
+            }
+        }
+    }
 }
 
 #[derive(clap::Subcommand, Debug)]
@@ -72,17 +668,76 @@ pub enum ConfigCommands {
 
 #[derive(clap::Subcommand, Debug)]
 pub enum ConfigSetters {
-    /// The model to use for ChatGPT [`gpt-3`, `gpt-4`, `gpt-4o`]
+    /// The model to use [`gpt-3`, `gpt-4`, `gpt-4o`, `claude-3.5-sonnet`, `claude-3.5-haiku`, `claude-3-opus`, `gemini-1.5-pro`, `gemini-1.5-flash`, `gemini-2.0-flash`, `ollama:<MODEL>`, `<provider>:<MODEL ID>` or an ID listed by `pgpt models list`]
     Model {
         #[arg(value_enum)]
         value: Model,
     },
-    /// The API key for OpenAI. Create one at https://platform.openai.com/api-keys
-    APIKey { value: String },
+    /// The API key for a provider. Create one for OpenAI at https://platform.openai.com/api-keys
+    APIKey {
+        value: String,
+        /// The provider the API key is for
+        #[arg(long, value_enum, default_value_t)]
+        provider: Provider,
+    },
+    /// The system prompt sent before the conversation with every query
+    SystemPrompt { value: String },
     /// The maximum number of prompt/response pairs to save in cache.
-    CacheLength { value: usize },
+    CacheLength {
+        value: usize,
+        /// Set it for this session only, instead of for every session without its own (optional)
+        #[arg(long, value_name = "NAME")]
+        session: Option<String>,
+    },
     /// The default number of prompt/response pairs to send as context with the query
-    Context { value: usize },
+    Context {
+        value: usize,
+        /// Set it for this session only, instead of for every session without its own (optional)
+        #[arg(long, value_name = "NAME")]
+        session: Option<String>,
+    },
+    /// The base URL of an OpenAI-compatible API (defaults to https://api.openai.com/v1)
+    BaseURL { value: String },
+    /// The Azure OpenAI resource endpoint (e.g. https://my-resource.openai.azure.com). Setting it sends OpenAI models through Azure
+    AzureEndpoint { value: String },
+    /// The Azure OpenAI API version
+    AzureAPIVersion { value: String },
+    /// The Azure OpenAI deployment to use for a model
+    AzureDeployment {
+        #[arg(value_enum)]
+        model: Model,
+        deployment: String,
+    },
+    /// The number of times a request is retried after a rate limit, server or network error
+    MaxRetries { value: u32 },
+    /// The longest time (in seconds) to wait before retrying a request
+    MaxRetryWait { value: u64 },
+    /// [0-2] The default sampling temperature. Higher values make responses more random
+    Temperature { value: f32 },
+    /// [0-1] The default nucleus sampling probability mass
+    TopP { value: f32 },
+    /// The default maximum number of tokens to generate
+    MaxTokens { value: u32 },
+    /// The default seed for deterministic sampling
+    Seed {
+        #[arg(allow_negative_numbers = true)]
+        value: i64,
+    },
+    /// The default stop sequences (up to 4)
+    Stop {
+        #[arg(required = true)]
+        values: Vec<String>,
+    },
+    /// [-2-2] The default presence penalty
+    PresencePenalty {
+        #[arg(allow_negative_numbers = true)]
+        value: f32,
+    },
+    /// [-2-2] The default frequency penalty
+    FrequencyPenalty {
+        #[arg(allow_negative_numbers = true)]
+        value: f32,
+    },
 }
 
 impl ConfigSetters {
@@ -90,14 +745,34 @@ impl ConfigSetters {
         let mut config = utils::load_config_file()?;
 
         match self {
-            Self::APIKey { value } => match value.trim() {
+            Self::APIKey { value, provider } => match value.trim() {
                 value if !value.is_empty() => {
-                    utils::save_api_key(&value)?;
+                    utils::save_api_key(*provider, value)?;
                     return Ok(());
                 }
                 e => return Err(anyhow::anyhow!("Received empty API key - {}", e)),
             },
-            Self::CacheLength { value } => {
+            Self::CacheLength {
+                value,
+                session: Some(session),
+            } => {
+                utils::validate_name("Session", session)?;
+                println!(
+                    "Setting {} for session {} to {}",
+                    "cache-length".cyan(),
+                    session.cyan(),
+                    value.to_string().cyan()
+                );
+                config
+                    .sessions
+                    .entry(session.clone())
+                    .or_default()
+                    .cache_length = Some(*value);
+            }
+            Self::CacheLength {
+                value,
+                session: None,
+            } => {
                 println!(
                     "Setting {} to {}",
                     "cache-length".cyan(),
@@ -110,7 +785,23 @@ impl ConfigSetters {
                 println!("Setting {} to {}", "model".cyan(), str_model.cyan());
                 config.model = str_model;
             }
-            Self::Context { value } => {
+            Self::Context {
+                value,
+                session: Some(session),
+            } => {
+                utils::validate_name("Session", session)?;
+                println!(
+                    "Setting {} for session {} to {}",
+                    "context".cyan(),
+                    session.cyan(),
+                    value.to_string().cyan()
+                );
+                config.sessions.entry(session.clone()).or_default().context = Some(*value);
+            }
+            Self::Context {
+                value,
+                session: None,
+            } => {
                 println!(
                     "Setting {} to {}",
                     "context".cyan(),
@@ -118,25 +809,178 @@ impl ConfigSetters {
                 );
                 config.context = *value;
             }
+            Self::SystemPrompt { value } => {
+                let system_prompt = value.trim();
+                if system_prompt.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "Received empty system prompt, use `pgpt config clear system-prompt` to remove it"
+                    ));
+                }
+                println!(
+                    "Setting {} to {}",
+                    "system-prompt".cyan(),
+                    system_prompt.cyan()
+                );
+                config.system_prompt = Some(system_prompt.to_string());
+            }
+            Self::BaseURL { value } => {
+                let base_url = value.trim().trim_end_matches('/');
+                if base_url.is_empty() {
+                    return Err(anyhow::anyhow!("Received empty base URL"));
+                }
+                println!("Setting {} to {}", "base-url".cyan(), base_url.cyan());
+                config.base_url = Some(base_url.to_string());
+            }
+            Self::AzureEndpoint { value } => {
+                let endpoint = value.trim().trim_end_matches('/');
+                if endpoint.is_empty() {
+                    return Err(anyhow::anyhow!("Received empty Azure endpoint"));
+                }
+                println!("Setting {} to {}", "azure-endpoint".cyan(), endpoint.cyan());
+                config
+                    .azure
+                    .get_or_insert_with(AzureConfig::default)
+                    .endpoint = endpoint.to_string();
+            }
+            Self::AzureAPIVersion { value } => {
+                println!("Setting {} to {}", "azure-api-version".cyan(), value.cyan());
+                config
+                    .azure
+                    .get_or_insert_with(AzureConfig::default)
+                    .api_version = value.trim().to_string();
+            }
+            Self::AzureDeployment { model, deployment } => {
+                println!(
+                    "Setting {} for {} to {}",
+                    "azure-deployment".cyan(),
+                    model.to_string().cyan(),
+                    deployment.cyan()
+                );
+                config
+                    .azure
+                    .get_or_insert_with(AzureConfig::default)
+                    .deployments
+                    .insert(model.to_string(), deployment.trim().to_string());
+            }
+            Self::MaxRetries { value } => {
+                println!(
+                    "Setting {} to {}",
+                    "max-retries".cyan(),
+                    value.to_string().cyan()
+                );
+                config.max_retries = *value;
+            }
+            Self::MaxRetryWait { value } => {
+                println!(
+                    "Setting {} to {}",
+                    "max-retry-wait".cyan(),
+                    format!("{}s", value).cyan()
+                );
+                config.max_retry_wait = *value;
+            }
+            Self::Temperature { value } => {
+                Self::set_sampling(&mut config, "temperature", value, |params| {
+                    params.temperature = Some(*value)
+                })?;
+            }
+            Self::TopP { value } => {
+                Self::set_sampling(&mut config, "top-p", value, |params| {
+                    params.top_p = Some(*value)
+                })?;
+            }
+            Self::MaxTokens { value } => {
+                Self::set_sampling(&mut config, "max-tokens", value, |params| {
+                    params.max_tokens = Some(*value)
+                })?;
+            }
+            Self::Seed { value } => {
+                Self::set_sampling(&mut config, "seed", value, |params| {
+                    params.seed = Some(*value)
+                })?;
+            }
+            Self::Stop { values } => {
+                Self::set_sampling(&mut config, "stop", &format!("{:?}", values), |params| {
+                    params.stop = Some(values.clone())
+                })?;
+            }
+            Self::PresencePenalty { value } => {
+                Self::set_sampling(&mut config, "presence-penalty", value, |params| {
+                    params.presence_penalty = Some(*value)
+                })?;
+            }
+            Self::FrequencyPenalty { value } => {
+                Self::set_sampling(&mut config, "frequency-penalty", value, |params| {
+                    params.frequency_penalty = Some(*value)
+                })?;
+            }
         };
         utils::save_config_file(&config)?;
         Ok(())
     }
+
+    /// Updates a sampling default, checking it against the provider of the configured model
+    ///
+    /// ### Arguments
+    /// - `config` - The config to update
+    /// - `name` - The name of the setting
+    /// - `value` - The new value (for display)
+    /// - `update` - Sets the value on the sampling defaults
+    fn set_sampling(
+        config: &mut ConfigJSON,
+        name: &str,
+        value: &impl std::fmt::Display,
+        update: impl FnOnce(&mut SamplingParams),
+    ) -> anyhow::Result<()> {
+        let mut sampling = config.sampling.clone();
+        update(&mut sampling);
+        let provider = Model::from_str(&config.model)
+            .map(|model| model.provider())
+            .unwrap_or_default();
+        sampling.validate(provider)?;
+        println!("Setting {} to {}", name.cyan(), value.to_string().cyan());
+        config.sampling = sampling;
+        Ok(())
+    }
 }
 
 #[derive(clap::Subcommand, Debug)]
 pub enum ConfigRemovers {
     /// Clear the saved API key
-    APIKey,
+    APIKey {
+        /// The provider the API key is for
+        #[arg(long, value_enum, default_value_t)]
+        provider: Provider,
+    },
     /// Clear the saved prompt/response cache
-    Cache,
+    Cache {
+        /// Clear the cache of this session instead of the current one (optional)
+        #[arg(long, value_name = "NAME")]
+        session: Option<String>,
+    },
+    /// Stop sending a system prompt
+    SystemPrompt,
+    /// Reset the API base URL to the default
+    BaseURL,
+    /// Clear the Azure OpenAI settings and send OpenAI models to OpenAI
+    Azure,
+    /// Reset the sampling defaults (temperature, top-p, max-tokens, seed, stop and penalties) to the provider defaults
+    Sampling,
 }
 
 impl ConfigRemovers {
     pub fn clear(&self) -> anyhow::Result<()> {
         match self {
-            Self::APIKey => utils::clear_api_key(),
-            Self::Cache => utils::clear_cache(),
+            Self::APIKey { provider } => utils::clear_api_key(*provider),
+            Self::Cache { session } => {
+                let config = utils::load_or_register_config_file()?;
+                let session = session.as_deref().unwrap_or(session::current_name(&config));
+                utils::validate_name("Session", session)?;
+                utils::clear_cache(session)
+            }
+            Self::SystemPrompt => utils::clear_system_prompt(),
+            Self::BaseURL => utils::clear_base_url(),
+            Self::Azure => utils::clear_azure(),
+            Self::Sampling => utils::clear_sampling(),
         }
     }
 }
@@ -145,56 +989,129 @@ impl ConfigRemovers {
 pub enum ConfigSettings {
     /// The model being used for ChatGPT
     Model,
-    /// The API key for OpenAI (attempting to show the key will display an encrypted version!)
-    APIKey,
+    /// The API key for a provider (attempting to show the key will display an encrypted version!)
+    APIKey {
+        /// The provider the API key is for
+        #[arg(long, value_enum, default_value_t)]
+        provider: Provider,
+    },
     /// The maximum number of prompt/response pairs to save in cache.
     CacheLength,
     /// All of the previously saved prompt/response pairs
-    Cache,
+    Cache {
+        /// Show the cache of this session instead of the current one (optional)
+        #[arg(long, value_name = "NAME")]
+        session: Option<String>,
+    },
+    /// The system prompt sent with every query
+    SystemPrompt,
     /// The default number of prompt/response pairs to send with the query
     Context,
+    /// The base URL of the API
+    BaseURL,
+    /// The Azure OpenAI settings
+    Azure,
+    /// The number of times a request is retried
+    MaxRetries,
+    /// The longest time (in seconds) to wait before retrying a request
+    MaxRetryWait,
+    /// The sampling defaults (temperature, top-p, max-tokens, seed, stop and penalties)
+    Sampling,
     /// All of the configuration values.
     All,
 }
 
 impl ConfigSettings {
     pub fn show(&self) -> anyhow::Result<()> {
-        let config = utils::load_config_file().unwrap_or_else(|_| {
-            utils::register_config_file().unwrap_or_else(|e| panic!("{:#?}", e))
-        });
-        let api_key = utils::load_api_key()
-            .unwrap_or_else(|_| utils::register_api_key().unwrap_or_else(|e| panic!("{:#?}", e)));
-        let encrypted = encrypt(api_key, utils::encryption_password(), nonce()?)?;
-        let enc_str = String::from_utf8_lossy(&encrypted);
+        let config = utils::load_or_register_config_file()?;
         match self {
             Self::Model => {
                 println!("{}: {}", "Model".cyan(), config.model);
             }
-            Self::APIKey => {
-                println!("{}: {}", "API Key (encrypted)".cyan(), enc_str);
+            Self::APIKey { provider } => {
+                let api_key = utils::load_or_register_api_key(*provider)?;
+                println!(
+                    "{}: {}",
+                    format!("{} API Key (encrypted)", provider).cyan(),
+                    encrypted_api_key(&api_key)?
+                );
             }
-            Self::Cache => {
-                let cache = utils::load_cache()?;
-                println!("{}:", "Cache".cyan());
+            Self::Cache { session } => {
+                let session = session.as_deref().unwrap_or(session::current_name(&config));
+                utils::validate_name("Session", session)?;
+                let cache = utils::load_cache(session)?;
+                println!("{} ({}):", "Cache".cyan(), session);
                 for (i, value) in cache.iter().enumerate() {
                     println!("{}", format!("Cached {}/{}", i + 1, cache.len()).cyan());
+                    if let Some(role) = &value.role {
+                        println!("{}: {}", "Role".yellow(), role);
+                    }
                     println!("{}: {}", "You said".yellow(), value.prompt);
+                    if !value.images.is_empty() {
+                        println!("{}: {}", "Images".yellow(), value.images.join(", "));
+                    }
                     println!("{}:\n{}", "GPT said".magenta(), value.response);
-                    println!("")
+                    println!()
                 }
             }
             Self::CacheLength => {
                 println!("{}: {}", "Cache Length".cyan(), config.cache_length);
             }
+            Self::SystemPrompt => {
+                println!(
+                    "{}: {}",
+                    "System Prompt".cyan(),
+                    config.system_prompt.as_deref().unwrap_or("Not set")
+                );
+            }
             Self::Context => {
                 println!("{}: {}", "Context".cyan(), config.context);
             }
+            Self::BaseURL => {
+                println!("{}: {}", "Base URL".cyan(), utils::load_base_url(&config));
+            }
+            Self::Azure => match &config.azure {
+                Some(azure) => azure.show(),
+                None => println!("{}: Not set", "Azure".cyan()),
+            },
+            Self::MaxRetries => {
+                println!("{}: {}", "Max Retries".cyan(), config.max_retries);
+            }
+            Self::MaxRetryWait => {
+                println!("{}: {}s", "Max Retry Wait".cyan(), config.max_retry_wait);
+            }
+            Self::Sampling => config.sampling.show(),
             Self::All => {
-                // let cache = utils::load_cache()?;
                 println!("{}: {}", "Model".cyan(), config.model);
-                println!("{}: {}", "API Key (encrypted)".cyan(), enc_str);
+                let key_providers = Provider::value_variants()
+                    .iter()
+                    .filter(|provider| provider.requires_api_key());
+                for provider in key_providers {
+                    let enc_str = match utils::load_api_key(*provider) {
+                        Ok(api_key) => encrypted_api_key(&api_key)?,
+                        Err(_) => String::from("Not set"),
+                    };
+                    println!(
+                        "{}: {}",
+                        format!("{} API Key (encrypted)", provider).cyan(),
+                        enc_str
+                    );
+                }
+                println!("{}: {}", "Session".cyan(), session::current_name(&config));
                 println!("{}: {}", "Cache Length".cyan(), config.cache_length);
                 println!("{}: {}", "Context".cyan(), config.context);
+                println!(
+                    "{}: {}",
+                    "System Prompt".cyan(),
+                    config.system_prompt.as_deref().unwrap_or("Not set")
+                );
+                println!("{}: {}", "Base URL".cyan(), utils::load_base_url(&config));
+                if let Some(azure) = &config.azure {
+                    azure.show();
+                }
+                println!("{}: {}", "Max Retries".cyan(), config.max_retries);
+                println!("{}: {}s", "Max Retry Wait".cyan(), config.max_retry_wait);
+                config.sampling.show();
                 println!(
                     "To display cache, run `{}`",
                     "pgpt config show cache".cyan()
@@ -205,76 +1122,309 @@ impl ConfigSettings {
     }
 }
 
+/// Encrypts the API key so it can be displayed
+fn encrypted_api_key(api_key: &str) -> anyhow::Result<String> {
+    let encrypted = encrypt(api_key, utils::encryption_password(), nonce()?)?;
+    Ok(String::from_utf8_lossy(&encrypted).to_string())
+}
+
+/// Builds the query from the positional words and stdin. Stdin is only read when the query is
+/// empty and stdin is not a terminal, or when a `-` word asks for it: a lone `-` makes the piped
+/// input the whole query, and a `-` after other words adds it below them.
+///
+/// ### Arguments
+/// - `words` - The positional query words
+fn read_query(words: &[String]) -> anyhow::Result<String> {
+    let read_stdin = words.iter().any(|word| word == "-");
+    let query = words
+        .iter()
+        .filter(|word| *word != "-")
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if !read_stdin && (!query.is_empty() || std::io::stdin().is_terminal()) {
+        return Ok(query);
+    }
+
+    let mut buffer = Vec::new();
+    std::io::stdin()
+        .read_to_end(&mut buffer)
+        .context("Failed to read stdin")?;
+    Ok(join_input(&query, &String::from_utf8_lossy(&buffer)))
+}
+
+/// Adds the input read from stdin below the query, between delimiter lines
+///
+/// ### Arguments
+/// - `query` - The query from the positional words
+/// - `input` - The input read from stdin
+fn join_input(query: &str, input: &str) -> String {
+    let input = input.trim_end();
+    match (query.is_empty(), input.trim().is_empty()) {
+        (_, true) => query.to_string(),
+        (true, false) => input.to_string(),
+        (false, false) => format!(
+            "{}\n\n--- Input from stdin ---\n{}\n--- End of input ---",
+            query, input
+        ),
+    }
+}
+
 pub struct QueryArgs {
     pub model: Option<Model>,
     pub query: String,
+    /// Overrides the configured system prompt
+    pub system: Option<String>,
+    /// The name of the role to use
+    pub role: Option<String>,
     pub cost: bool,
     pub context: Option<usize>,
+    /// The session to use instead of the current one
+    pub session: Option<String>,
     pub show_context: bool,
+    pub no_stream: bool,
+    pub base_url: Option<String>,
+    pub tools: bool,
+    pub images: Vec<PathBuf>,
+    pub files: Vec<PathBuf>,
+    /// The sampling parameters set on the command line (they override the configured defaults)
+    pub sampling: SamplingParams,
+    pub json: bool,
+    pub json_schema: Option<PathBuf>,
+    /// How many choices to generate
+    pub choices: u32,
+    pub pick: Option<u32>,
+    /// How to show token log probabilities, when they were asked for
+    pub logprobs: Option<LogprobsDisplay>,
+    pub top_logprobs: Option<u32>,
+}
+
+impl Default for QueryArgs {
+    fn default() -> Self {
+        Self {
+            model: None,
+            query: String::new(),
+            system: None,
+            role: None,
+            cost: false,
+            context: None,
+            session: None,
+            show_context: false,
+            no_stream: false,
+            base_url: None,
+            tools: false,
+            images: Vec::new(),
+            files: Vec::new(),
+            sampling: SamplingParams::default(),
+            json: false,
+            json_schema: None,
+            choices: 1,
+            pick: None,
+            logprobs: None,
+            top_logprobs: None,
+        }
+    }
 }
 
 pub enum ParsedArgs {
     Query { args: Arc<QueryArgs> },
+    Chat { args: Arc<QueryArgs> },
     Config { config: ConfigCommands },
+    Models { models: ModelsCommands },
+    Pricing { pricing: PricingCommands },
+    Template { template: TemplateCommands },
+    Session { session: SessionCommands },
+    Role { role: RoleCommands },
 }
 
 #[derive(Debug)]
 pub struct Config {
-    pub api_key: String,
     pub model: Model,
     pub cache_length: usize,
     pub context: usize,
+    pub base_url: String,
+    pub azure: Option<AzureConfig>,
+    pub retry_policy: RetryPolicy,
+    pub sampling: SamplingParams,
+    pub pricing: PricingTable,
+    pub system_prompt: Option<String>,
+    /// The session used when none is given
+    pub current_session: String,
+    pub sessions: std::collections::BTreeMap<String, SessionSettings>,
 }
 
 impl Config {
-    /// Attempts to load config from env vars then config file, otherwise prompts for user to input key
+    /// Attempts to load config from env vars then config file
     pub fn load_config() -> anyhow::Result<Arc<Self>> {
-        let api_key = utils::load_api_key()
-            .unwrap_or_else(|_| utils::register_api_key().unwrap_or_else(|e| panic!("{:#?}", e)));
-        let config_json = utils::load_config_file().unwrap_or_else(|_| {
-            utils::register_config_file().unwrap_or_else(|e| panic!("{:#?}", e))
-        });
-        let model = Model::from_str(&config_json.model).map_err(|e| anyhow::anyhow!("{:#?}", e))?;
+        let config_json = utils::load_or_register_config_file()?;
+        let model = Model::from_str(&config_json.model)?;
+        let base_url = utils::load_base_url(&config_json);
 
         // Creating cache file if it doesn't exist (not loading because only to be used when needed)
         utils::register_cache()?;
 
+        let current_session = session::current_name(&config_json).to_string();
         let config = Self {
-            api_key,
             model,
             cache_length: config_json.cache_length,
             context: config_json.context,
+            base_url,
+            azure: config_json.azure,
+            retry_policy: RetryPolicy {
+                max_retries: config_json.max_retries,
+                max_wait: std::time::Duration::from_secs(config_json.max_retry_wait),
+            },
+            sampling: config_json.sampling,
+            pricing: PricingTable::load()?,
+            system_prompt: config_json.system_prompt,
+            current_session,
+            sessions: config_json.sessions,
         };
         Ok(Arc::new(config))
     }
 
+    /// Gets a session with its settings
+    ///
+    /// ### Arguments
+    /// - `name` - The name of the session, or `None` for the current one
+    pub fn session(&self, name: Option<&str>) -> Result<Session, PgptError> {
+        let name = name.unwrap_or(&self.current_session);
+        Session::new(
+            name,
+            self.sessions.get(name),
+            self.cache_length,
+            self.context,
+        )
+    }
+
+    /// Attempts to load the API key for the provider from env vars then the key file, otherwise prompts for user to input key
+    ///
+    /// ### Arguments
+    /// - `provider` - The provider the API key is for
+    pub fn api_key(&self, provider: Provider) -> anyhow::Result<String> {
+        utils::load_or_register_api_key(provider)
+    }
+
     /// Parses CLI arguments
-    pub fn parse_args() -> ParsedArgs {
-        let cli = CLI::parse();
+    pub fn parse_args() -> anyhow::Result<ParsedArgs> {
+        let cli = match CLI::try_parse() {
+            Ok(cli) => cli,
+            Err(e) => match e
+                .source()
+                .and_then(|source| source.downcast_ref::<PgptError>())
+            {
+                // A corrupt models.json found while parsing `--model` keeps its exit code
+                Some(PgptError::ConfigCorrupt(message)) => {
+                    return Err(PgptError::ConfigCorrupt(message.clone()).into())
+                }
+                _ => e.exit(),
+            },
+        };
         match cli.command {
             Commands::Query {
                 query,
+                system,
+                role,
                 cost,
                 model,
                 context,
+                session,
                 show_context,
+                no_stream,
+                base_url,
+                tools,
+                images,
+                files,
+                temperature,
+                top_p,
+                max_tokens,
+                seed,
+                stop,
+                presence_penalty,
+                frequency_penalty,
+                json,
+                json_schema,
+                choices,
+                pick,
+                logprobs,
+                top_logprobs,
             } => {
-                let query = query.join(" ");
-                // let context = context.unwrap_or(0);
+                let query = read_query(&query)?;
                 let args = QueryArgs {
                     query,
+                    system,
+                    role,
                     model,
                     cost,
                     context,
+                    session,
                     show_context,
+                    no_stream,
+                    base_url,
+                    tools,
+                    images,
+                    files,
+                    sampling: SamplingParams {
+                        temperature,
+                        top_p,
+                        max_tokens,
+                        seed,
+                        stop: if stop.is_empty() { None } else { Some(stop) },
+                        presence_penalty,
+                        frequency_penalty,
+                    },
+                    json,
+                    json_schema,
+                    choices,
+                    pick,
+                    logprobs: logprobs.or(top_logprobs.map(|_| LogprobsDisplay::Table)),
+                    top_logprobs,
                 };
-                ParsedArgs::Query {
+                Ok(ParsedArgs::Query {
                     args: Arc::new(args),
-                }
+                })
+            }
+            Commands::Chat {
+                model,
+                role,
+                system,
+                session,
+                context,
+                no_stream,
+                base_url,
+            } => {
+                let args = QueryArgs {
+                    model,
+                    role,
+                    system,
+                    session,
+                    context,
+                    no_stream,
+                    base_url,
+                    ..QueryArgs::default()
+                };
+                Ok(ParsedArgs::Chat {
+                    args: Arc::new(args),
+                })
             }
-            Commands::Config { config_commands } => ParsedArgs::Config {
+            Commands::Config { config_commands } => Ok(ParsedArgs::Config {
                 config: config_commands,
-            },
+            }),
+            Commands::Template { template_commands } => Ok(ParsedArgs::Template {
+                template: template_commands,
+            }),
+            Commands::Models { models_commands } => Ok(ParsedArgs::Models {
+                models: models_commands,
+            }),
+            Commands::Pricing { pricing_commands } => Ok(ParsedArgs::Pricing {
+                pricing: pricing_commands,
+            }),
+            Commands::Session { session_commands } => Ok(ParsedArgs::Session {
+                session: session_commands,
+            }),
+            Commands::Role { role_commands } => Ok(ParsedArgs::Role {
+                role: role_commands,
+            }),
         }
     }
 
@@ -294,10 +1444,179 @@ pub struct ConfigJSON {
     pub model: String,
     pub cache_length: usize,
     pub context: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub azure: Option<AzureConfig>,
+    #[serde(default = "ConfigJSON::default_max_retries")]
+    pub max_retries: u32,
+    /// In seconds
+    #[serde(default = "ConfigJSON::default_max_retry_wait")]
+    pub max_retry_wait: u64,
+    /// The default sampling parameters
+    #[serde(default, skip_serializing_if = "SamplingParams::is_empty")]
+    pub sampling: SamplingParams,
+    /// Sent before the conversation with every query
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    /// The current session, which is the default one when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    /// The settings of the sessions that have their own, keyed by name
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub sessions: std::collections::BTreeMap<String, SessionSettings>,
+}
+
+impl ConfigJSON {
+    /// Gets a session with its settings
+    ///
+    /// ### Arguments
+    /// - `name` - The name of the session
+    pub fn session(&self, name: &str) -> Result<Session, PgptError> {
+        Session::new(
+            name,
+            self.sessions.get(name),
+            self.cache_length,
+            self.context,
+        )
+    }
+
+    fn default_max_retries() -> u32 {
+        RetryPolicy::default().max_retries
+    }
+
+    fn default_max_retry_wait() -> u64 {
+        RetryPolicy::default().max_wait.as_secs()
+    }
+}
+
+/// Settings for sending OpenAI models through Azure OpenAI
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct AzureConfig {
+    #[serde(default)]
+    pub endpoint: String,
+    #[serde(default = "AzureConfig::default_api_version")]
+    pub api_version: String,
+    /// Deployment names keyed by model (e.g. `gpt-4o`)
+    #[serde(default)]
+    pub deployments: std::collections::BTreeMap<String, String>,
+}
+
+impl Default for AzureConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: String::new(),
+            api_version: Self::default_api_version(),
+            deployments: std::collections::BTreeMap::new(),
+        }
+    }
+}
+
+impl AzureConfig {
+    fn default_api_version() -> String {
+        String::from("2024-10-21")
+    }
+
+    /// Returns whether OpenAI models should be sent through Azure
+    pub fn is_enabled(&self) -> bool {
+        !self.endpoint.is_empty()
+    }
+
+    /// Gets the deployment for the model
+    ///
+    /// ### Arguments
+    /// - `model` - The model to get the deployment for
+    pub fn deployment(&self, model: &Model) -> anyhow::Result<&str> {
+        match self.deployments.get(&model.to_string()) {
+            Some(deployment) => Ok(deployment),
+            None => Err(anyhow::anyhow!(
+                "No Azure deployment set for {}. Set one with `pgpt config set azure-deployment {} <DEPLOYMENT>`",
+                model,
+                model
+            )),
+        }
+    }
+
+    /// Prints the models that have a deployment, which are the only OpenAI models that can be used
+    /// in Azure mode
+    pub fn list_deployments(&self) -> anyhow::Result<()> {
+        if self.deployments.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No Azure deployments have been set, set one with `pgpt config set azure-deployment <MODEL> <DEPLOYMENT>`"
+            ));
+        }
+        println!(
+            "{}",
+            format!(
+                "{} deployments of {}",
+                Provider::Azure.to_string().magenta(),
+                self.endpoint
+            )
+            .cyan()
+        );
+        for (model, deployment) in self.deployments.iter() {
+            println!("{} ({})", model, deployment);
+        }
+        println!();
+        println!(
+            "Add another with {}",
+            "pgpt config set azure-deployment <MODEL> <DEPLOYMENT>".cyan()
+        );
+        Ok(())
+    }
+
+    fn show(&self) {
+        println!("{}: {}", "Azure Endpoint".cyan(), self.endpoint);
+        println!("{}: {}", "Azure API Version".cyan(), self.api_version);
+        for (model, deployment) in self.deployments.iter() {
+            println!(
+                "{}: {}",
+                format!("Azure Deployment ({})", model).cyan(),
+                deployment
+            );
+        }
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct CacheValue {
     pub prompt: String,
     pub response: String,
+    /// The paths of the images attached to the prompt (the images themselves are not cached)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+    /// The role the response was generated with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn piped_input_is_added_below_the_query() {
+        assert_eq!(
+            join_input("why is this failing", "error: oops\n\n"),
+            "why is this failing\n\n--- Input from stdin ---\nerror: oops\n--- End of input ---"
+        );
+    }
+
+    #[test]
+    fn piped_input_alone_is_the_query() {
+        assert_eq!(join_input("", "  indented prompt\n"), "  indented prompt");
+    }
+
+    #[test]
+    fn empty_input_leaves_the_query() {
+        assert_eq!(join_input("hello", " \n\n"), "hello");
+    }
+
+    #[test]
+    fn words_without_a_dash_do_not_read_stdin() {
+        let words = vec![String::from("hello"), String::from("world")];
+
+        // Reading stdin here would block or swallow the test runner's input
+        assert_eq!(read_query(&words).unwrap(), "hello world");
+    }
 }
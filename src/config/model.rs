@@ -1,34 +1,127 @@
-use clap::Parser;
+use crate::config::utils;
+use crate::error::PgptError;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// ChatGPT model
-#[derive(Debug, Parser, Clone, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub enum Model {
     GPT3,
     GPT4,
     GPT4o,
+    Claude35Sonnet,
+    Claude35Haiku,
+    Claude3Opus,
+    Gemini15Pro,
+    Gemini15Flash,
+    Gemini20Flash,
+    /// A model served by a local Ollama instance (e.g. `ollama:llama3`)
+    Ollama(String),
+    /// Any other model a provider offers, by its API ID (e.g. `openai:o3`)
+    Other {
+        provider: Provider,
+        id: String,
+    },
+}
+
+/// The vendor API that serves a model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Default, serde::Serialize)]
+pub enum Provider {
+    #[default]
+    #[value(name = "openai")]
+    OpenAI,
+    Anthropic,
+    Gemini,
+    Ollama,
+    Azure,
 }
 
 impl FromStr for Model {
-    type Err = String;
+    type Err = PgptError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s, utils::load_models_cache)
+    }
+}
+
+impl Model {
+    /// Parses a model alias, `<provider>:<model ID>` or an ID saved by `pgpt models list`
+    ///
+    /// ### Arguments
+    /// - `s` - The model to parse
+    /// - `load_models` - Loads the saved models, only called for IDs that are not an alias
+    fn parse(
+        s: &str,
+        load_models: impl FnOnce() -> Result<ModelsCache, PgptError>,
+    ) -> Result<Self, PgptError> {
         match s {
             "gpt-3" => Ok(Model::GPT3),
             "gpt-4" => Ok(Model::GPT4),
             "gpt-4o" => Ok(Model::GPT4o),
-            _ => Err(format!("'{}' is not a valid model", s)),
+            "claude-3.5-sonnet" => Ok(Model::Claude35Sonnet),
+            "claude-3.5-haiku" => Ok(Model::Claude35Haiku),
+            "claude-3-opus" => Ok(Model::Claude3Opus),
+            "gemini-1.5-pro" => Ok(Model::Gemini15Pro),
+            "gemini-1.5-flash" => Ok(Model::Gemini15Flash),
+            "gemini-2.0-flash" => Ok(Model::Gemini20Flash),
+            _ => match s.split_once(':') {
+                Some(("ollama", name)) if !name.trim().is_empty() => {
+                    Ok(Model::Ollama(name.trim().to_string()))
+                }
+                Some((provider, id)) if !id.trim().is_empty() => {
+                    match Provider::from_str(provider, true) {
+                        Ok(provider) if provider != Provider::Azure => Ok(Model::Other {
+                            provider,
+                            id: id.trim().to_string(),
+                        }),
+                        _ => Model::discovered(s, &load_models()?),
+                    }
+                }
+                _ => Model::discovered(s, &load_models()?),
+            },
         }
     }
 }
 
+impl std::fmt::Display for Model {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::GPT3 => "gpt-3",
+            Self::GPT4 => "gpt-4",
+            Self::GPT4o => "gpt-4o",
+            Self::Claude35Sonnet => "claude-3.5-sonnet",
+            Self::Claude35Haiku => "claude-3.5-haiku",
+            Self::Claude3Opus => "claude-3-opus",
+            Self::Gemini15Pro => "gemini-1.5-pro",
+            Self::Gemini15Flash => "gemini-1.5-flash",
+            Self::Gemini20Flash => "gemini-2.0-flash",
+            Self::Ollama(name) => return write!(f, "ollama:{}", name),
+            Self::Other { provider, id } => return write!(f, "{}:{}", provider.id(), id),
+        };
+        write!(f, "{}", name)
+    }
+}
+
 impl Model {
-    /// Returns the string representation of the model
-    pub fn to_string(&self) -> String {
-        match self {
-            Self::GPT3 => String::from("gpt-3"),
-            Self::GPT4 => String::from("gpt-4"),
-            Self::GPT4o => String::from("gpt-4o"),
+    /// Finds a model ID in the models saved by `pgpt models list`
+    ///
+    /// ### Arguments
+    /// - `id` - The API ID of the model
+    /// - `models` - The saved models
+    fn discovered(id: &str, models: &ModelsCache) -> Result<Self, PgptError> {
+        match models.find(id) {
+            Some(Provider::Ollama) => Ok(Model::Ollama(id.to_string())),
+            Some(provider) => Ok(Model::Other {
+                provider,
+                id: id.to_string(),
+            }),
+            None => Err(PgptError::InvalidModel(format!(
+                "'{}' is not a model alias, an ID listed by `pgpt models list` or `<provider>:<model ID>`",
+                id
+            ))),
         }
     }
 
@@ -38,24 +131,426 @@ impl Model {
             Self::GPT3 => String::from("gpt-3.5-turbo"),
             Self::GPT4 => String::from("gpt-4-turbo"),
             Self::GPT4o => String::from("gpt-4o"),
+            Self::Claude35Sonnet => String::from("claude-3-5-sonnet-latest"),
+            Self::Claude35Haiku => String::from("claude-3-5-haiku-latest"),
+            Self::Claude3Opus => String::from("claude-3-opus-latest"),
+            Self::Gemini15Pro => String::from("gemini-1.5-pro"),
+            Self::Gemini15Flash => String::from("gemini-1.5-flash"),
+            Self::Gemini20Flash => String::from("gemini-2.0-flash"),
+            Self::Ollama(name) => name.clone(),
+            Self::Other { id, .. } => id.clone(),
+        }
+    }
+
+    /// Returns the provider that serves the model
+    pub fn provider(&self) -> Provider {
+        match self {
+            Self::GPT3 | Self::GPT4 | Self::GPT4o => Provider::OpenAI,
+            Self::Claude35Sonnet | Self::Claude35Haiku | Self::Claude3Opus => Provider::Anthropic,
+            Self::Gemini15Pro | Self::Gemini15Flash | Self::Gemini20Flash => Provider::Gemini,
+            Self::Ollama(_) => Provider::Ollama,
+            Self::Other { provider, .. } => *provider,
+        }
+    }
+
+    /// Returns what the model can do with pgpt. For Ollama and other discovered models only what
+    /// pgpt can't send to their provider is known, so the rest is left to the provider to reject.
+    pub fn capabilities(&self) -> ModelCapabilities {
+        let provider = ModelCapabilities::provider_default(self.provider());
+        match self {
+            Self::GPT3 => ModelCapabilities {
+                context_window: Some(16_385),
+                max_output_tokens: Some(4_096),
+                vision: Some(false),
+                json_schema: Some(false),
+                ..provider
+            },
+            Self::GPT4 => ModelCapabilities {
+                context_window: Some(128_000),
+                max_output_tokens: Some(4_096),
+                json_schema: Some(false),
+                ..provider
+            },
+            Self::GPT4o => ModelCapabilities {
+                context_window: Some(128_000),
+                max_output_tokens: Some(16_384),
+                ..provider
+            },
+            Self::Claude35Sonnet => ModelCapabilities {
+                context_window: Some(200_000),
+                max_output_tokens: Some(8_192),
+                ..provider
+            },
+            Self::Claude35Haiku => ModelCapabilities {
+                context_window: Some(200_000),
+                max_output_tokens: Some(8_192),
+                vision: Some(false),
+                ..provider
+            },
+            Self::Claude3Opus => ModelCapabilities {
+                context_window: Some(200_000),
+                max_output_tokens: Some(4_096),
+                ..provider
+            },
+            Self::Gemini15Pro => ModelCapabilities {
+                context_window: Some(2_097_152),
+                max_output_tokens: Some(8_192),
+                ..provider
+            },
+            Self::Gemini15Flash | Self::Gemini20Flash => ModelCapabilities {
+                context_window: Some(1_048_576),
+                max_output_tokens: Some(8_192),
+                ..provider
+            },
+            Self::Ollama(_) | Self::Other { .. } => provider.unverified(),
+        }
+    }
+}
+
+/// What a model supports and how large its requests can be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelCapabilities {
+    /// The most tokens the prompt and response can add up to (unknown for some models)
+    pub context_window: Option<u32>,
+    /// The most tokens the model can generate in one response (unknown for some models)
+    pub max_output_tokens: Option<u32>,
+    /// Images can be attached to the prompt (unknown for some models)
+    pub vision: Option<bool>,
+    /// The model can call tools (unknown for some models)
+    pub tools: Option<bool>,
+    /// The response can be forced to be a JSON object (unknown for some models)
+    pub json_mode: Option<bool>,
+    /// The response can be forced to match a JSON Schema (unknown for some models)
+    pub json_schema: Option<bool>,
+    /// The response can be streamed as it is generated (unknown for some models)
+    pub streaming: Option<bool>,
+    /// System messages are accepted (unknown for some models)
+    pub system_role: Option<bool>,
+}
+
+impl ModelCapabilities {
+    /// Returns what pgpt supports for models of a provider
+    ///
+    /// ### Arguments
+    /// - `provider` - The provider of the model
+    fn provider_default(provider: Provider) -> Self {
+        match provider {
+            Provider::OpenAI | Provider::Azure => Self {
+                context_window: None,
+                max_output_tokens: None,
+                vision: Some(true),
+                tools: Some(true),
+                json_mode: Some(true),
+                json_schema: Some(true),
+                streaming: Some(true),
+                system_role: Some(true),
+            },
+            // Tools and JSON output are not sent to Anthropic
+            Provider::Anthropic => Self {
+                context_window: None,
+                max_output_tokens: None,
+                vision: Some(true),
+                tools: Some(false),
+                json_mode: Some(false),
+                json_schema: Some(false),
+                streaming: Some(true),
+                system_role: Some(true),
+            },
+            Provider::Gemini | Provider::Ollama => Self {
+                context_window: None,
+                max_output_tokens: None,
+                vision: Some(true),
+                tools: Some(false),
+                json_mode: Some(true),
+                json_schema: Some(true),
+                streaming: Some(true),
+                system_role: Some(true),
+            },
+        }
+    }
+
+    /// Marks what the provider supports in pgpt as unknown, for models whose features are not
+    /// known. What pgpt can't send to the provider stays unsupported.
+    fn unverified(self) -> Self {
+        let unknown = |supported: Option<bool>| supported.filter(|supported| !supported);
+        Self {
+            vision: unknown(self.vision),
+            tools: unknown(self.tools),
+            json_mode: unknown(self.json_mode),
+            json_schema: unknown(self.json_schema),
+            streaming: unknown(self.streaming),
+            system_role: unknown(self.system_role),
+            ..self
         }
     }
+}
+
+impl std::fmt::Display for Provider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::OpenAI => "OpenAI",
+            Self::Anthropic => "Anthropic",
+            Self::Gemini => "Gemini",
+            Self::Ollama => "Ollama",
+            Self::Azure => "Azure OpenAI",
+        };
+        write!(f, "{}", name)
+    }
+}
 
-    /// Returns the price ($) per token for the model input
-    pub fn prompt_cost(&self) -> f64 {
+impl Provider {
+    /// Returns the name used for the provider on the command line and in model IDs (e.g. `openai`)
+    pub fn id(&self) -> &'static str {
         match self {
-            Self::GPT3 => 0.5 / 1e6,
-            Self::GPT4 => 10.0 / 1e6,
-            Self::GPT4o => 5.0 / 1e6,
+            Self::OpenAI => "openai",
+            Self::Anthropic => "anthropic",
+            Self::Gemini => "gemini",
+            Self::Ollama => "ollama",
+            Self::Azure => "azure",
         }
     }
 
-    /// Returns the price ($) per token for the model output
-    pub fn completion_cost(&self) -> f64 {
+    /// Returns whether requests to the provider need an API key
+    pub fn requires_api_key(&self) -> bool {
+        self.api_key_env().is_some()
+    }
+
+    /// Returns the environment variable that can hold the provider's API key
+    pub fn api_key_env(&self) -> Option<&'static str> {
         match self {
-            Self::GPT3 => 1.5 / 1e6,
-            Self::GPT4 => 30.0 / 1e6,
-            Self::GPT4o => 15.0 / 1e6,
+            Self::OpenAI => Some("OPENAI_API_KEY"),
+            Self::Anthropic => Some("ANTHROPIC_API_KEY"),
+            Self::Gemini => Some("GEMINI_API_KEY"),
+            Self::Ollama => None,
+            Self::Azure => Some("AZURE_OPENAI_API_KEY"),
+        }
+    }
+
+    /// Returns the name of the file the encrypted API key is saved to
+    pub fn api_key_file(&self) -> Option<&'static str> {
+        match self {
+            Self::OpenAI => Some("key.enc"),
+            Self::Anthropic => Some("anthropic_key.enc"),
+            Self::Gemini => Some("gemini_key.enc"),
+            Self::Ollama => None,
+            Self::Azure => Some("azure_key.enc"),
+        }
+    }
+
+    /// Returns where an API key can be created for the provider
+    pub fn api_key_url(&self) -> Option<&'static str> {
+        match self {
+            Self::OpenAI => Some("https://platform.openai.com/api-keys"),
+            Self::Anthropic => Some("https://console.anthropic.com/settings/keys"),
+            Self::Gemini => Some("https://aistudio.google.com/app/apikey"),
+            Self::Ollama => None,
+            Self::Azure => {
+                Some("https://portal.azure.com (Keys and Endpoint of your Azure OpenAI resource)")
+            }
+        }
+    }
+}
+
+/// The model IDs fetched from a provider by `pgpt models list`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DiscoveredModels {
+    /// When the models were fetched (seconds since the Unix epoch)
+    pub fetched_at: u64,
+    pub models: Vec<String>,
+}
+
+impl DiscoveredModels {
+    /// Creates the list of models fetched just now
+    ///
+    /// ### Arguments
+    /// - `models` - The model IDs
+    pub fn new(models: Vec<String>) -> Self {
+        Self {
+            fetched_at: now(),
+            models,
+        }
+    }
+
+    /// Describes how long ago the models were fetched (e.g. `3 hours ago`)
+    pub fn age(&self) -> String {
+        let secs = now().saturating_sub(self.fetched_at);
+        let (amount, unit) = match secs {
+            0..=59 => return String::from("just now"),
+            60..=3_599 => (secs / 60, "minute"),
+            3_600..=86_399 => (secs / 3_600, "hour"),
+            _ => (secs / 86_400, "day"),
+        };
+        format!(
+            "{} {}{} ago",
+            amount,
+            unit,
+            if amount == 1 { "" } else { "s" }
+        )
+    }
+}
+
+/// The models discovered for each provider, saved next to the config
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ModelsCache {
+    #[serde(flatten)]
+    providers: BTreeMap<String, DiscoveredModels>,
+}
+
+impl ModelsCache {
+    /// Gets the models saved for a provider
+    pub fn get(&self, provider: Provider) -> Option<&DiscoveredModels> {
+        self.providers.get(provider.id())
+    }
+
+    /// Replaces the models saved for a provider
+    pub fn insert(&mut self, provider: Provider, models: DiscoveredModels) {
+        self.providers.insert(provider.id().to_string(), models);
+    }
+
+    /// Finds the provider that offers a model ID
+    ///
+    /// ### Arguments
+    /// - `id` - The API ID of the model
+    pub fn find(&self, id: &str) -> Option<Provider> {
+        self.providers
+            .iter()
+            .find(|(_, discovered)| discovered.models.iter().any(|model| model == id))
+            .and_then(|(provider, _)| Provider::from_str(provider, true).ok())
+    }
+}
+
+/// Seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Saved models with `o3` from OpenAI, `claude-opus-4-1` from Anthropic and `llama3.2` from Ollama
+    fn saved_models() -> Result<ModelsCache, PgptError> {
+        let mut models = ModelsCache::default();
+        models.insert(
+            Provider::OpenAI,
+            DiscoveredModels::new(vec![String::from("o3")]),
+        );
+        models.insert(
+            Provider::Anthropic,
+            DiscoveredModels::new(vec![String::from("claude-opus-4-1")]),
+        );
+        models.insert(
+            Provider::Ollama,
+            DiscoveredModels::new(vec![String::from("llama3.2")]),
+        );
+        Ok(models)
+    }
+
+    /// Fails the test if the saved models are loaded
+    fn not_loaded() -> Result<ModelsCache, PgptError> {
+        panic!("the saved models were loaded")
+    }
+
+    #[test]
+    fn aliases_are_parsed_without_loading_the_saved_models() {
+        for alias in [
+            "gpt-3",
+            "gpt-4",
+            "gpt-4o",
+            "claude-3.5-sonnet",
+            "claude-3.5-haiku",
+            "claude-3-opus",
+            "gemini-1.5-pro",
+            "gemini-1.5-flash",
+            "gemini-2.0-flash",
+        ] {
+            let model = Model::parse(alias, not_loaded).unwrap();
+            assert_eq!(model.to_string(), alias);
+        }
+        assert_eq!(
+            Model::parse("claude-3.5-haiku", not_loaded)
+                .unwrap()
+                .api_model(),
+            "claude-3-5-haiku-latest"
+        );
+    }
+
+    #[test]
+    fn provider_prefixed_ids_are_parsed_without_loading_the_saved_models() {
+        let model = Model::parse("ollama: llama3 ", not_loaded).unwrap();
+        assert!(matches!(&model, Model::Ollama(name) if name == "llama3"));
+
+        let model = Model::parse("Anthropic:claude-opus-4-1", not_loaded).unwrap();
+        assert_eq!(model.provider(), Provider::Anthropic);
+        assert_eq!(model.api_model(), "claude-opus-4-1");
+        assert_eq!(model.to_string(), "anthropic:claude-opus-4-1");
+    }
+
+    #[test]
+    fn saved_ids_are_found_with_their_provider() {
+        let model = Model::parse("o3", saved_models).unwrap();
+        assert!(matches!(&model, Model::Other { provider: Provider::OpenAI, id } if id == "o3"));
+
+        let model = Model::parse("claude-opus-4-1", saved_models).unwrap();
+        assert_eq!(model.provider(), Provider::Anthropic);
+
+        let model = Model::parse("llama3.2", saved_models).unwrap();
+        assert!(matches!(&model, Model::Ollama(name) if name == "llama3.2"));
+    }
+
+    #[test]
+    fn unknown_ids_are_invalid_models() {
+        for id in ["gpt-5-turbo", "azure:gpt-4o", "nope:model", "openai:", ""] {
+            let error = Model::parse(id, saved_models).unwrap_err();
+            assert_eq!(error.exit_code(), 13, "{}", id);
+        }
+    }
+
+    #[test]
+    fn corrupt_saved_models_are_reported() {
+        let corrupt = || Err(PgptError::ConfigCorrupt(String::from("models.json")));
+
+        assert_eq!(Model::parse("o3", corrupt).unwrap_err().exit_code(), 20);
+        assert!(Model::parse("gpt-4o", corrupt).is_ok());
+    }
+
+    #[test]
+    fn built_in_models_have_known_capabilities() {
+        let gpt4o = Model::GPT4o.capabilities();
+        assert_eq!(gpt4o.vision, Some(true));
+        assert_eq!(gpt4o.tools, Some(true));
+        assert_eq!(gpt4o.context_window, Some(128_000));
+
+        assert_eq!(Model::GPT3.capabilities().vision, Some(false));
+        assert_eq!(Model::Claude35Sonnet.capabilities().tools, Some(false));
+    }
+
+    #[test]
+    fn discovered_model_features_are_unknown() {
+        let o3 = Model::Other {
+            provider: Provider::OpenAI,
+            id: String::from("o3"),
+        }
+        .capabilities();
+        assert_eq!(o3.vision, None);
+        assert_eq!(o3.tools, None);
+        assert_eq!(o3.json_schema, None);
+        assert_eq!(o3.context_window, None);
+
+        // pgpt doesn't send tools to Ollama, whatever the model supports
+        let llama = Model::Ollama(String::from("llama3.2")).capabilities();
+        assert_eq!(llama.vision, None);
+        assert_eq!(llama.tools, Some(false));
+
+        let claude = Model::Other {
+            provider: Provider::Anthropic,
+            id: String::from("claude-opus-4-1"),
         }
+        .capabilities();
+        assert_eq!(claude.json_mode, Some(false));
+        assert_eq!(claude.system_role, None);
     }
 }
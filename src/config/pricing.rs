@@ -0,0 +1,278 @@
+use crate::config::model::{Model, Provider};
+use crate::config::utils;
+use crate::error::PgptError;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The prices ($ per million tokens) pgpt ships with, keyed by the API ID of the model
+const DEFAULT_PRICES: [(&str, ModelPrice); 20] = [
+    ("gpt-3.5-turbo", ModelPrice::new(0.5, None, 1.5)),
+    ("gpt-4-turbo", ModelPrice::new(10.0, None, 30.0)),
+    ("gpt-4o", ModelPrice::new(2.5, Some(1.25), 10.0)),
+    ("gpt-4o-mini", ModelPrice::new(0.15, Some(0.075), 0.6)),
+    ("gpt-4.1", ModelPrice::new(2.0, Some(0.5), 8.0)),
+    ("gpt-4.1-mini", ModelPrice::new(0.4, Some(0.1), 1.6)),
+    ("gpt-4.1-nano", ModelPrice::new(0.1, Some(0.025), 0.4)),
+    ("o1", ModelPrice::new(15.0, Some(7.5), 60.0)),
+    ("o3", ModelPrice::new(2.0, Some(0.5), 8.0)),
+    ("o3-mini", ModelPrice::new(1.1, Some(0.55), 4.4)),
+    ("o4-mini", ModelPrice::new(1.1, Some(0.275), 4.4)),
+    (
+        "claude-3-5-sonnet-latest",
+        ModelPrice::new(3.0, Some(0.3), 15.0),
+    ),
+    (
+        "claude-3-5-haiku-latest",
+        ModelPrice::new(0.8, Some(0.08), 4.0),
+    ),
+    (
+        "claude-3-opus-latest",
+        ModelPrice::new(15.0, Some(1.5), 75.0),
+    ),
+    ("claude-sonnet-4-0", ModelPrice::new(3.0, Some(0.3), 15.0)),
+    ("claude-opus-4-0", ModelPrice::new(15.0, Some(1.5), 75.0)),
+    ("gemini-1.5-pro", ModelPrice::new(1.25, Some(0.3125), 5.0)),
+    (
+        "gemini-1.5-flash",
+        ModelPrice::new(0.075, Some(0.01875), 0.3),
+    ),
+    ("gemini-2.0-flash", ModelPrice::new(0.1, Some(0.025), 0.4)),
+    ("gemini-2.5-pro", ModelPrice::new(1.25, Some(0.31), 10.0)),
+];
+
+/// The cache write price relative to the input price, when none is set
+const CACHE_WRITE_MULTIPLIER: f64 = 1.25;
+
+/// The prices ($ per million tokens) of a model
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    pub input: f64,
+    /// Input tokens read from the provider's prompt cache (defaults to the input price)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_input: Option<f64>,
+    /// Input tokens written to the provider's prompt cache (defaults to 1.25 times the input
+    /// price, what Anthropic charges for 5-minute cache writes)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_write: Option<f64>,
+    pub output: f64,
+    /// Output tokens spent on reasoning (defaults to the output price)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<f64>,
+}
+
+impl ModelPrice {
+    const fn new(input: f64, cached_input: Option<f64>, output: f64) -> Self {
+        Self {
+            input,
+            cached_input,
+            cache_write: None,
+            output,
+            reasoning: None,
+        }
+    }
+
+    /// The price of a cached input token
+    pub fn cached_input(&self) -> f64 {
+        self.cached_input.unwrap_or(self.input)
+    }
+
+    /// The price of an input token written to the cache
+    pub fn cache_write(&self) -> f64 {
+        self.cache_write
+            .unwrap_or(self.input * CACHE_WRITE_MULTIPLIER)
+    }
+
+    /// The price of a reasoning token
+    pub fn reasoning(&self) -> f64 {
+        self.reasoning.unwrap_or(self.output)
+    }
+}
+
+/// The prices of every model pgpt knows about: the defaults with the user's `pricing.json` on top
+#[derive(Debug, Clone)]
+pub struct PricingTable {
+    prices: BTreeMap<String, ModelPrice>,
+    /// The model IDs whose prices come from `pricing.json`
+    overrides: Vec<String>,
+}
+
+impl PricingTable {
+    /// The prices pgpt ships with, without any overrides
+    pub fn defaults() -> Self {
+        Self {
+            prices: DEFAULT_PRICES
+                .iter()
+                .map(|(id, price)| (id.to_string(), *price))
+                .collect(),
+            overrides: Vec::new(),
+        }
+    }
+
+    /// Loads the default prices and applies the overrides in `pricing.json`, if it exists
+    pub fn load() -> anyhow::Result<Self> {
+        let Self {
+            mut prices,
+            mut overrides,
+        } = Self::defaults();
+
+        let pricing_path = utils::pricing_file_path();
+        if pricing_path.exists() {
+            let buffer = std::fs::read(&pricing_path)?;
+            let user_prices: BTreeMap<String, ModelPrice> = serde_json::from_slice(&buffer)
+                .map_err(|e| PgptError::ConfigCorrupt(format!("{:?}: {}", pricing_path, e)))?;
+            for (id, price) in user_prices {
+                overrides.push(id.clone());
+                prices.insert(id, price);
+            }
+        }
+        Ok(Self { prices, overrides })
+    }
+
+    /// Gets the prices of a model. Ollama models are free to run, and models without a price are
+    /// `None`.
+    ///
+    /// ### Arguments
+    /// - `model` - The model to get the prices of
+    pub fn get(&self, model: &Model) -> Option<ModelPrice> {
+        if model.provider() == Provider::Ollama {
+            return Some(ModelPrice::new(0.0, None, 0.0));
+        }
+        self.prices.get(&model.api_model()).copied()
+    }
+
+    /// Prints the price of every model
+    pub fn show(&self) {
+        println!(
+            "{}",
+            "Prices in $ per million tokens (cached input and reasoning default to the input and output prices, cache writes to 1.25 times the input price)"
+                .cyan()
+        );
+        let width = self.prices.keys().map(String::len).max().unwrap_or(0);
+        println!(
+            "{:<width$}  {:>8}  {:>13}  {:>12}  {:>8}  {:>9}",
+            "Model", "Input", "Cached input", "Cache write", "Output", "Reasoning"
+        );
+        for (id, price) in self.prices.iter() {
+            let line = format!(
+                "{:<width$}  {:>8}  {:>13}  {:>12}  {:>8}  {:>9}",
+                id,
+                price.input,
+                price.cached_input(),
+                price.cache_write(),
+                price.output,
+                price.reasoning()
+            );
+            if self.overrides.contains(id) {
+                println!("{} {}", line, "(custom)".yellow());
+            } else {
+                println!("{}", line);
+            }
+        }
+        println!();
+        println!(
+            "Override or add prices in {}",
+            format!("{:?}", utils::pricing_file_path()).cyan()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpt::{GPTCompletionTokensDetails, GPTPromptTokensDetails, GPTUsage};
+
+    fn usage(
+        prompt: i32,
+        cached: i32,
+        cache_write: i32,
+        completion: i32,
+        reasoning: i32,
+    ) -> GPTUsage {
+        GPTUsage {
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+            total_tokens: prompt + completion,
+            prompt_tokens_details: GPTPromptTokensDetails {
+                cached_tokens: cached,
+                cache_write_tokens: cache_write,
+            },
+            completion_tokens_details: GPTCompletionTokensDetails {
+                reasoning_tokens: reasoning,
+            },
+        }
+    }
+
+    fn assert_cost(cost: Option<f64>, expected: f64) {
+        let cost = cost.expect("the model has a price");
+        assert!((cost - expected).abs() < 1e-12, "{} != {}", cost, expected);
+    }
+
+    #[test]
+    fn unset_prices_fall_back() {
+        let price = ModelPrice::new(3.0, None, 15.0);
+
+        assert_eq!(price.cached_input(), 3.0);
+        assert_eq!(price.cache_write(), 3.75);
+        assert_eq!(price.reasoning(), 15.0);
+    }
+
+    #[test]
+    fn input_and_output_tokens_are_priced() {
+        let pricing = PricingTable::defaults();
+        // gpt-4o: $2.50 input, $10 output per million tokens
+        let cost = usage(1_000, 0, 0, 500, 0).total_cost(&Model::GPT4o, &pricing);
+
+        assert_cost(cost, (1_000.0 * 2.5 + 500.0 * 10.0) / 1e6);
+    }
+
+    #[test]
+    fn cached_and_cache_write_tokens_are_priced_separately() {
+        let pricing = PricingTable::defaults();
+        // claude-3-5-sonnet: $3 input, $0.30 cached, $3.75 cache write, $15 output
+        let cost = usage(10_000, 6_000, 3_000, 200, 0).total_cost(&Model::Claude35Sonnet, &pricing);
+
+        assert_cost(
+            cost,
+            (1_000.0 * 3.0 + 6_000.0 * 0.3 + 3_000.0 * 3.75 + 200.0 * 15.0) / 1e6,
+        );
+    }
+
+    #[test]
+    fn reasoning_tokens_use_the_reasoning_price() {
+        let mut pricing = PricingTable::defaults();
+        pricing.prices.insert(
+            String::from("o3"),
+            ModelPrice {
+                reasoning: Some(20.0),
+                ..ModelPrice::new(2.0, Some(0.5), 8.0)
+            },
+        );
+        let model = Model::Other {
+            provider: Provider::OpenAI,
+            id: String::from("o3"),
+        };
+        let cost = usage(1_000, 400, 0, 1_000, 600).total_cost(&model, &pricing);
+
+        assert_cost(
+            cost,
+            (600.0 * 2.0 + 400.0 * 0.5 + 400.0 * 8.0 + 600.0 * 20.0) / 1e6,
+        );
+    }
+
+    #[test]
+    fn ollama_is_free_and_unknown_models_have_no_cost() {
+        let pricing = PricingTable::defaults();
+        let tokens = usage(1_000, 0, 0, 1_000, 0);
+
+        assert_cost(
+            tokens.total_cost(&Model::Ollama(String::from("llama3")), &pricing),
+            0.0,
+        );
+        let unknown = Model::Other {
+            provider: Provider::OpenAI,
+            id: String::from("my-fine-tuned-model"),
+        };
+        assert_eq!(tokens.total_cost(&unknown, &pricing), None);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::config::model::Model;
+use crate::config::utils;
+use crate::error::PgptError;
+use crate::provider::SamplingParams;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// The longest part of a system prompt shown by `pgpt role list`
+const PREVIEW_LENGTH: usize = 60;
+
+/// A named system prompt, which can also fix the model and sampling defaults
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Role {
+    pub system_prompt: String,
+    /// Used instead of the configured model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Take precedence over the configured sampling defaults
+    #[serde(default, skip_serializing_if = "SamplingParams::is_empty")]
+    pub sampling: SamplingParams,
+}
+
+impl Role {
+    /// Gets the model of the role, if it has one
+    pub fn model(&self) -> Result<Option<Model>, PgptError> {
+        self.model.as_deref().map(Model::from_str).transpose()
+    }
+
+    /// Prints the role
+    ///
+    /// ### Arguments
+    /// - `name` - The name of the role
+    pub fn show(&self, name: &str) {
+        println!("{}: {}", "Role".cyan(), name);
+        println!(
+            "{}: {}",
+            "Model".cyan(),
+            self.model.as_deref().unwrap_or("Configured model")
+        );
+        self.sampling.show();
+        println!("{}:\n{}", "System Prompt".cyan(), self.system_prompt);
+    }
+
+    /// The first line of the system prompt, shortened to fit on one line
+    fn preview(&self) -> String {
+        let first_line = self.system_prompt.lines().next().unwrap_or_default();
+        if first_line.chars().count() > PREVIEW_LENGTH
+            || self.system_prompt.lines().nth(1).is_some()
+        {
+            let preview: String = first_line.chars().take(PREVIEW_LENGTH).collect();
+            format!("{}...", preview)
+        } else {
+            first_line.to_string()
+        }
+    }
+}
+
+/// The roles saved in `roles.json`, keyed by name
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(transparent)]
+pub struct Roles {
+    roles: BTreeMap<String, Role>,
+}
+
+impl Roles {
+    /// Loads the saved roles, which are empty if none have been added
+    pub fn load() -> anyhow::Result<Self> {
+        let roles_path = utils::roles_file_path();
+        if !roles_path.exists() {
+            return Ok(Self::default());
+        }
+        let buffer = std::fs::read(&roles_path)?;
+        let roles: Self = serde_json::from_slice(&buffer)
+            .map_err(|e| PgptError::ConfigCorrupt(format!("{:?}: {}", roles_path, e)))?;
+        Ok(roles)
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let roles_path = utils::roles_file_path();
+        let prefix = roles_path.parent().unwrap();
+        std::fs::create_dir_all(prefix)?;
+        let json_str = serde_json::to_string_pretty(self)?;
+        std::fs::write(&roles_path, json_str)?;
+        Ok(())
+    }
+
+    /// Gets a role by name
+    ///
+    /// ### Arguments
+    /// - `name` - The name of the role
+    pub fn get(&self, name: &str) -> Result<&Role, PgptError> {
+        self.roles.get(name).ok_or_else(|| Self::not_found(name))
+    }
+
+    /// Adds a role, replacing any role with the same name. Returns whether a role was replaced.
+    ///
+    /// ### Arguments
+    /// - `name` - The name of the role
+    /// - `role` - The role to add
+    pub fn insert(&mut self, name: &str, role: Role) -> Result<bool, PgptError> {
+        utils::validate_name("Role", name)?;
+        Ok(self.roles.insert(name.to_string(), role).is_some())
+    }
+
+    /// Removes a role by name
+    ///
+    /// ### Arguments
+    /// - `name` - The name of the role
+    pub fn remove(&mut self, name: &str) -> Result<Role, PgptError> {
+        self.roles.remove(name).ok_or_else(|| Self::not_found(name))
+    }
+
+    /// The error for a role that has not been added
+    fn not_found(name: &str) -> PgptError {
+        PgptError::InvalidArgument(format!("No role named `{}`, see `pgpt role list`", name))
+    }
+
+    /// Prints the name, model and start of the system prompt of every role
+    pub fn list(&self) {
+        if self.roles.is_empty() {
+            println!(
+                "No roles have been added yet, add one with {}",
+                "pgpt role add <NAME> <SYSTEM PROMPT>".cyan()
+            );
+            return;
+        }
+        for (name, role) in self.roles.iter() {
+            match &role.model {
+                Some(model) => println!("{} ({})", name.cyan(), model),
+                None => println!("{}", name.cyan()),
+            }
+            println!("  {}", role.preview());
+        }
+    }
+}
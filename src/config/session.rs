@@ -0,0 +1,102 @@
+use crate::config::utils;
+use crate::config::ConfigJSON;
+use crate::error::PgptError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// The session used until another one is switched to. Its history is kept in `cache.json`.
+pub const DEFAULT_SESSION: &str = "default";
+
+/// The settings of a session. Unset values use the configured `cache-length` and `context`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SessionSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_length: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<usize>,
+}
+
+/// A named conversation with its own history
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub name: String,
+    /// The maximum number of prompt/response pairs to save in the history
+    pub cache_length: usize,
+    /// The default number of prompt/response pairs to send as context
+    pub context: usize,
+}
+
+impl Session {
+    /// Gets a session with its settings, falling back to the configured defaults
+    ///
+    /// ### Arguments
+    /// - `name` - The name of the session
+    /// - `settings` - The settings of the session, if it has any
+    /// - `cache_length` - The configured `cache-length`
+    /// - `context` - The configured `context`
+    pub fn new(
+        name: &str,
+        settings: Option<&SessionSettings>,
+        cache_length: usize,
+        context: usize,
+    ) -> Result<Self, PgptError> {
+        utils::validate_name("Session", name)?;
+        Ok(Self {
+            name: name.to_string(),
+            cache_length: settings
+                .and_then(|settings| settings.cache_length)
+                .unwrap_or(cache_length),
+            context: settings
+                .and_then(|settings| settings.context)
+                .unwrap_or(context),
+        })
+    }
+}
+
+/// Gets the name of the session that is used when none is given
+///
+/// ### Arguments
+/// - `config` - The configuration the current session is saved in
+pub fn current_name(config: &ConfigJSON) -> &str {
+    config.session.as_deref().unwrap_or(DEFAULT_SESSION)
+}
+
+/// Gets the names of every session: the default one, the ones with a history and the ones with
+/// their own settings
+///
+/// ### Arguments
+/// - `config` - The configuration the session settings are saved in
+pub fn names(config: &ConfigJSON) -> anyhow::Result<Vec<String>> {
+    let mut names = BTreeSet::new();
+    let sessions_dir = utils::sessions_dir_path();
+    if sessions_dir.is_dir() {
+        for entry in std::fs::read_dir(&sessions_dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                if let Some(stem) = path.file_stem() {
+                    names.insert(stem.to_string_lossy().to_string());
+                }
+            }
+        }
+    }
+    names.extend(config.sessions.keys().cloned());
+    names.insert(current_name(config).to_string());
+    names.remove(DEFAULT_SESSION);
+    Ok(std::iter::once(DEFAULT_SESSION.to_string())
+        .chain(names)
+        .collect())
+}
+
+/// The error for a session that does not exist
+///
+/// ### Arguments
+/// - `name` - The name of the session
+pub fn not_found(name: &str) -> PgptError {
+    PgptError::InvalidArgument(format!(
+        "No session named `{}`, see `pgpt session list`",
+        name
+    ))
+}
@@ -0,0 +1,333 @@
+use crate::config::utils;
+use crate::error::PgptError;
+use colored::*;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// A prompt file with `{{name}}` placeholders, saved in the templates directory
+pub struct Template {
+    pub name: String,
+    pub text: String,
+}
+
+impl Template {
+    /// Loads a template by name (its file name without the extension) from the templates directory,
+    /// or from a path
+    ///
+    /// ### Arguments
+    /// - `name` - The name of the template or the path to a template file
+    pub fn load(name: &str) -> anyhow::Result<Self> {
+        let path = match Self::saved_paths()?
+            .into_iter()
+            .find(|path| Self::name_of(path) == name)
+        {
+            Some(path) => path,
+            None if Path::new(name).is_file() => PathBuf::from(name),
+            None => {
+                return Err(PgptError::InvalidArgument(format!(
+                    "No template named `{}` in {:?}, see `pgpt template list`",
+                    name,
+                    utils::templates_dir_path()
+                ))
+                .into())
+            }
+        };
+        Self::from_path(&path)
+    }
+
+    /// Reads a template file
+    ///
+    /// ### Arguments
+    /// - `path` - The path of the template file
+    fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read template {:?}: {}", path, e))?;
+        Ok(Self {
+            name: Self::name_of(path),
+            text,
+        })
+    }
+
+    /// Loads every template in the templates directory
+    pub fn load_all() -> anyhow::Result<Vec<Self>> {
+        Self::saved_paths()?
+            .iter()
+            .map(|path| Self::from_path(path))
+            .collect()
+    }
+
+    /// The paths of the files in the templates directory, sorted by name
+    fn saved_paths() -> anyhow::Result<Vec<PathBuf>> {
+        let templates_dir = utils::templates_dir_path();
+        if !templates_dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(&templates_dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// The name of a template file, which is its file name without the extension
+    fn name_of(path: &Path) -> String {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Gets the names of the variables in the template, in the order they first appear. Fails if a
+    /// placeholder is not closed or its name is not valid.
+    pub fn variables(&self) -> Result<Vec<String>, PgptError> {
+        let mut variables: Vec<String> = Vec::new();
+        for part in self.parts()? {
+            if let TemplatePart::Variable(name) = part {
+                if !variables.iter().any(|variable| variable == name) {
+                    variables.push(name.to_string());
+                }
+            }
+        }
+        Ok(variables)
+    }
+
+    /// Replaces the placeholders with the values of the variables. Fails if a variable is missing.
+    ///
+    /// ### Arguments
+    /// - `values` - The values of the variables, keyed by name
+    pub fn render(&self, values: &BTreeMap<String, String>) -> Result<String, PgptError> {
+        let missing: Vec<String> = self
+            .variables()?
+            .into_iter()
+            .filter(|name| !values.contains_key(name))
+            .collect();
+        if !missing.is_empty() {
+            return Err(PgptError::InvalidArgument(format!(
+                "Template `{}` needs {}, pass {} with `--var <NAME>=<VALUE>`",
+                self.name,
+                missing
+                    .iter()
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                if missing.len() == 1 { "it" } else { "them" }
+            )));
+        }
+
+        let mut rendered = String::new();
+        for part in self.parts()? {
+            match part {
+                TemplatePart::Text(text) => rendered.push_str(text),
+                TemplatePart::Variable(name) => rendered.push_str(&values[name]),
+            }
+        }
+        Ok(rendered)
+    }
+
+    /// Splits the template into text and placeholders
+    fn parts(&self) -> Result<Vec<TemplatePart<'_>>, PgptError> {
+        let mut parts = Vec::new();
+        let mut rest = self.text.as_str();
+        while let Some(start) = rest.find("{{") {
+            let offset = self.text.len() - rest.len() + start;
+            let line = self.text[..offset].matches('\n').count() + 1;
+            let Some(end) = rest[start..].find("}}") else {
+                return Err(self.invalid(line, "`{{` is never closed with `}}`"));
+            };
+            let name = rest[start + 2..start + end].trim();
+            let valid_name = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid_name {
+                return Err(self.invalid(
+                    line,
+                    &format!(
+                        "`{{{{{}}}}}` is not a valid variable, names can only contain letters, digits, `-` and `_`",
+                        name
+                    ),
+                ));
+            }
+            parts.push(TemplatePart::Text(&rest[..start]));
+            parts.push(TemplatePart::Variable(name));
+            rest = &rest[start + end + 2..];
+        }
+        parts.push(TemplatePart::Text(rest));
+        Ok(parts)
+    }
+
+    /// The error for a template that cannot be parsed
+    fn invalid(&self, line: usize, reason: &str) -> PgptError {
+        PgptError::InvalidArgument(format!(
+            "Template `{}` is invalid on line {}: {}",
+            self.name, line, reason
+        ))
+    }
+
+    /// Prints the name and variables of the template
+    pub fn show(&self) {
+        match self.variables() {
+            Ok(variables) if variables.is_empty() => println!("{}", self.name.cyan()),
+            Ok(variables) => println!("{} ({})", self.name.cyan(), variables.join(", ")),
+            Err(_) => println!("{} {}", self.name.cyan(), "(invalid)".red()),
+        }
+    }
+}
+
+/// A piece of a template
+enum TemplatePart<'a> {
+    Text(&'a str),
+    /// The name of a variable
+    Variable(&'a str),
+}
+
+/// Parses a `--var NAME=VALUE` argument. A value starting with `@` is replaced with the contents of
+/// the file at that path, and `@@` starts a value with a literal `@`.
+///
+/// ### Arguments
+/// - `arg` - The argument to parse
+pub fn parse_var(arg: &str) -> Result<(String, String), String> {
+    let Some((name, value)) = arg.split_once('=') else {
+        return Err(format!("expected NAME=VALUE, got `{}`", arg));
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(format!("missing the variable name in `{}`", arg));
+    }
+    let value = if let Some(literal) = value.strip_prefix("@@") {
+        format!("@{}", literal)
+    } else if let Some(path) = value.strip_prefix('@') {
+        std::fs::read_to_string(path).map_err(|e| format!("failed to read `{}`: {}", path, e))?
+    } else {
+        value.to_string()
+    };
+    Ok((name.to_string(), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(text: &str) -> Template {
+        Template {
+            name: String::from("test"),
+            text: text.to_string(),
+        }
+    }
+
+    fn values(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn variables_are_listed_once_in_order() {
+        let template = template("{{b}} and {{ a }}, then {{b}} again and {{a-1_x}}");
+
+        assert_eq!(template.variables().unwrap(), ["b", "a", "a-1_x"]);
+    }
+
+    #[test]
+    fn placeholders_are_replaced() {
+        let template = template("Summarize this {{lang}} file:\n\n{{file}}\n\nIn {{lang}}.");
+        let rendered = template
+            .render(&values(&[("lang", "rust"), ("file", "fn main() {}")]))
+            .unwrap();
+
+        assert_eq!(
+            rendered,
+            "Summarize this rust file:\n\nfn main() {}\n\nIn rust."
+        );
+    }
+
+    #[test]
+    fn values_are_not_parsed_as_templates() {
+        let rendered = template("{{a}}")
+            .render(&values(&[("a", "{{b}}")]))
+            .unwrap();
+
+        assert_eq!(rendered, "{{b}}");
+    }
+
+    #[test]
+    fn text_without_placeholders_is_kept() {
+        let template = template("A lone }} and { braces }");
+
+        assert!(template.variables().unwrap().is_empty());
+        assert_eq!(
+            template.render(&BTreeMap::new()).unwrap(),
+            "A lone }} and { braces }"
+        );
+    }
+
+    #[test]
+    fn missing_variables_are_errors() {
+        let error = template("{{a}} {{b}} {{c}}")
+            .render(&values(&[("b", "2")]))
+            .unwrap_err();
+
+        assert_eq!(error.exit_code(), 2);
+        assert_eq!(
+            error.to_string(),
+            "Invalid argument: Template `test` needs `a`, `c`, pass them with `--var <NAME>=<VALUE>`"
+        );
+    }
+
+    #[test]
+    fn unterminated_placeholder_is_an_error() {
+        let error = template("line one\n{{name").variables().unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Invalid argument: Template `test` is invalid on line 2: `{{` is never closed with `}}`"
+        );
+    }
+
+    #[test]
+    fn invalid_variable_names_are_errors() {
+        for text in ["{{}}", "{{ }}", "{{a b}}", "{{a.b}}", "{{{a}}}"] {
+            assert!(template(text).variables().is_err(), "{} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn vars_are_split_on_the_first_equals_sign() {
+        assert_eq!(
+            parse_var("query=a=b&c=d").unwrap(),
+            (String::from("query"), String::from("a=b&c=d"))
+        );
+        assert_eq!(
+            parse_var(" name =").unwrap(),
+            (String::from("name"), String::new())
+        );
+        assert_eq!(
+            parse_var("email=@@me").unwrap(),
+            (String::from("email"), String::from("@me"))
+        );
+    }
+
+    #[test]
+    fn invalid_vars_are_errors() {
+        assert!(parse_var("novalue").is_err());
+        assert!(parse_var("=value").is_err());
+        assert!(parse_var("file=@/does/not/exist").is_err());
+    }
+
+    #[test]
+    fn var_values_are_read_from_files() {
+        let path = std::env::temp_dir().join(format!("pgpt-template-var-{}", std::process::id()));
+        std::fs::write(&path, "from a file").unwrap();
+        let parsed = parse_var(&format!("file=@{}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            parsed.unwrap(),
+            (String::from("file"), String::from("from a file"))
+        );
+    }
+}
@@ -1,8 +1,14 @@
+use crate::config::model::{ModelsCache, Provider};
+use crate::config::session::DEFAULT_SESSION;
 use crate::config::{CacheValue, ConfigJSON};
 use crate::encryption::{decrypt, encrypt, nonce};
+use crate::error::PgptError;
+use crate::gpt::DEFAULT_BASE_URL;
+use crate::provider::retry::RetryPolicy;
+use crate::provider::SamplingParams;
 use anyhow::Context;
 use colored::*;
-use std::io::{Read, Write};
+use std::io::{IsTerminal, Read, Write};
 
 /// Attempts to load the configuration json file.
 pub fn load_config_file() -> anyhow::Result<ConfigJSON> {
@@ -10,11 +16,20 @@ pub fn load_config_file() -> anyhow::Result<ConfigJSON> {
     let mut file = std::fs::File::open(&config_path)?;
     let mut buffer = Vec::new();
     file.read_to_end(&mut buffer)?;
-    let contents = String::from_utf8(buffer)?;
-    let json: ConfigJSON = serde_json::from_str(contents.as_str())?;
+    let json: ConfigJSON = serde_json::from_slice(&buffer)
+        .map_err(|e| PgptError::ConfigCorrupt(format!("{:?}: {}", config_path, e)))?;
     Ok(json)
 }
 
+/// Loads the configuration json file, creating it with default values if it doesn't exist
+pub fn load_or_register_config_file() -> anyhow::Result<ConfigJSON> {
+    if config_file_path().exists() {
+        load_config_file()
+    } else {
+        register_config_file()
+    }
+}
+
 /// Creates the config file with default values
 pub fn register_config_file() -> anyhow::Result<ConfigJSON> {
     let config_path = config_file_path();
@@ -22,8 +37,16 @@ pub fn register_config_file() -> anyhow::Result<ConfigJSON> {
         model: String::from("gpt-3"),
         cache_length: 5,
         context: 0,
+        base_url: None,
+        azure: None,
+        max_retries: RetryPolicy::default().max_retries,
+        max_retry_wait: RetryPolicy::default().max_wait.as_secs(),
+        sampling: SamplingParams::default(),
+        system_prompt: None,
+        session: None,
+        sessions: std::collections::BTreeMap::new(),
     };
-    println!(
+    eprintln!(
         "Creating configuration file with default values at {}",
         format!("{:?}", config_path).cyan()
     );
@@ -31,55 +54,85 @@ pub fn register_config_file() -> anyhow::Result<ConfigJSON> {
     let prefix = config_path.parent().unwrap();
     std::fs::create_dir_all(prefix)?;
     let mut file = std::fs::File::create(&config_path)?;
-    file.write(&json_str.as_bytes())
+    file.write_all(json_str.as_bytes())
         .with_context(|| format!("Could not write config to {:?}", config_path))?;
     Ok(config)
 }
 
 pub fn save_config_file(config: &ConfigJSON) -> anyhow::Result<()> {
     let config_path = config_file_path();
-    let mut file = std::fs::File::create(&config_path)
-        .with_context(|| format!("Could not open config file"))?;
+    let mut file = std::fs::File::create(&config_path).context("Could not open config file")?;
     let config_str = serde_json::to_string(config)?;
-    file.write(&config_str.as_bytes())?;
+    file.write_all(config_str.as_bytes())?;
     println!("{}", "Saved config successfully!".green());
     Ok(())
 }
 
 /// Attempts to load API key from env variable or config file
-pub fn load_api_key() -> anyhow::Result<String> {
-    if let Ok(key) = read_api_key_from_env() {
+///
+/// ### Arguments
+/// - `provider` - The provider the API key is for
+pub fn load_api_key(provider: Provider) -> anyhow::Result<String> {
+    if let Ok(key) = read_api_key_from_env(provider) {
         return Ok(key);
     }
 
-    let config_path = api_file_path();
+    let config_path = api_file_path(provider)?;
     let mut buffer = Vec::new();
     let mut file = std::fs::File::open(&config_path)?;
     file.read_to_end(&mut buffer)?;
-    let api_key_buf = decrypt(buffer, encryption_password())?;
-    let api_key = String::from_utf8(api_key_buf)?;
+    let api_key_buf = decrypt(buffer, encryption_password())
+        .map_err(|e| PgptError::KeyDecryptFailed(format!("{:?}: {}", config_path, e)))?;
+    let api_key = String::from_utf8(api_key_buf)
+        .map_err(|e| PgptError::KeyDecryptFailed(format!("{:?}: {}", config_path, e)))?;
 
     Ok(api_key)
 }
 
+/// Attempts to load API key from env variable or config file, otherwise prompts user for it
+///
+/// ### Arguments
+/// - `provider` - The provider the API key is for
+pub fn load_or_register_api_key(provider: Provider) -> anyhow::Result<String> {
+    match load_api_key(provider) {
+        Ok(api_key) => Ok(api_key),
+        // A key that exists but can't be read shouldn't be silently replaced
+        Err(e) if e.is::<PgptError>() => Err(e),
+        // Prompting would read the key from piped input
+        Err(_) if !std::io::stdin().is_terminal() => Err(PgptError::Authentication(format!(
+            "No {} API key found. Set `{}` or run `pgpt config set api-key` first",
+            provider,
+            provider.api_key_env().unwrap_or_default()
+        ))
+        .into()),
+        Err(_) => register_api_key(provider),
+    }
+}
+
 /// Prompts user for API key and encrypts/writes it to file
-pub fn register_api_key() -> anyhow::Result<String> {
-    println!();
-    println!("OpenAI API key not found!");
-    println!();
-    println!(
+///
+/// ### Arguments
+/// - `provider` - The provider the API key is for
+pub fn register_api_key(provider: Provider) -> anyhow::Result<String> {
+    let (Some(key_env), Some(key_url)) = (provider.api_key_env(), provider.api_key_url()) else {
+        return Err(no_api_key_error(provider));
+    };
+    eprintln!();
+    eprintln!("{} API key not found!", provider);
+    eprintln!();
+    eprintln!(
         "You need to enter an {} which will be encrypted and saved locally!",
-        "OpenAI API key".cyan()
+        format!("{} API key", provider).cyan()
     );
-    println!("You can create an API key at https://platform.openai.com/api-keys");
-    println!();
-    println!(
+    eprintln!("You can create an API key at {}", key_url);
+    eprintln!();
+    eprintln!(
         "If you don't want to save it, you can pass your API key to the environment variable `{}`!",
-        "OPENAI_API_KEY".cyan()
+        key_env.cyan()
     );
-    println!();
-    println!();
-    println!("{}", "Enter your API key:".bright_cyan());
+    eprintln!();
+    eprintln!();
+    eprintln!("{}", "Enter your API key:".bright_cyan());
     let mut api_key = String::new();
     std::io::stdin()
         .read_line(&mut api_key)
@@ -87,61 +140,89 @@ pub fn register_api_key() -> anyhow::Result<String> {
 
     match api_key.trim() {
         api_key if !api_key.is_empty() => {
-            save_api_key(&api_key)?;
+            save_api_key(provider, api_key)?;
             Ok(api_key.to_string())
         }
         e => Err(anyhow::anyhow!("Received empty API key - {}", e)),
     }
-    // Ok(String::new())
 }
 
-/// Attempts to load cache file
-pub fn load_cache() -> anyhow::Result<Vec<CacheValue>> {
-    let cache_path = cache_file_path();
+/// Attempts to load the history of a session, which is empty if nothing has been saved to it
+///
+/// ### Arguments
+/// - `session` - The name of the session
+pub fn load_cache(session: &str) -> anyhow::Result<Vec<CacheValue>> {
+    let cache_path = cache_file_path(session);
+    if !cache_path.exists() {
+        return Ok(Vec::new());
+    }
     let mut file = std::fs::File::open(&cache_path)?;
     let mut buffer = Vec::new();
     file.read_to_end(&mut buffer)?;
-    let str_cache = String::from_utf8(buffer)?;
-    let cache: Vec<CacheValue> = serde_json::from_str(&str_cache)?;
+    let cache: Vec<CacheValue> = serde_json::from_slice(&buffer)
+        .map_err(|e| PgptError::ConfigCorrupt(format!("{:?}: {}", cache_path, e)))?;
     Ok(cache)
 }
 
-/// Creates empty cache file if it doesn't exist
+/// Loads the models saved by `pgpt models list`, which is empty if it has never been run
+pub fn load_models_cache() -> Result<ModelsCache, PgptError> {
+    let models_path = models_file_path();
+    if !models_path.exists() {
+        return Ok(ModelsCache::default());
+    }
+    let buffer = std::fs::read(&models_path)
+        .map_err(|e| PgptError::ConfigCorrupt(format!("{:?}: {}", models_path, e)))?;
+    let models: ModelsCache = serde_json::from_slice(&buffer)
+        .map_err(|e| PgptError::ConfigCorrupt(format!("{:?}: {}", models_path, e)))?;
+    Ok(models)
+}
+
+pub fn save_models_cache(models: &ModelsCache) -> anyhow::Result<()> {
+    let models_path = models_file_path();
+    let prefix = models_path.parent().unwrap();
+    std::fs::create_dir_all(prefix)?;
+    let json_str = serde_json::to_string_pretty(models)?;
+    std::fs::write(&models_path, json_str)?;
+    Ok(())
+}
+
+/// Creates empty cache file of the default session if it doesn't exist
 pub fn register_cache() -> anyhow::Result<()> {
-    let cache_path = cache_file_path();
+    let cache_path = cache_file_path(DEFAULT_SESSION);
     if !cache_path.exists() {
         let prefix = cache_path.parent().unwrap();
-        std::fs::create_dir_all(&prefix)?;
+        std::fs::create_dir_all(prefix)?;
         let mut file = std::fs::File::create(cache_path)?;
         let empty: Vec<CacheValue> = vec![];
         let json_str = serde_json::to_string(&empty)?;
-        file.write(&json_str.as_bytes())?;
-        println!("{}", "Created cache file successfully!".green());
+        file.write_all(json_str.as_bytes())?;
+        eprintln!("{}", "Created cache file successfully!".green());
     }
 
     Ok(())
 }
 
-pub fn save_cache(cache: Vec<CacheValue>) -> anyhow::Result<()> {
-    let cache_path = cache_file_path();
+pub fn save_cache(session: &str, cache: Vec<CacheValue>) -> anyhow::Result<()> {
+    let cache_path = cache_file_path(session);
+    let prefix = cache_path.parent().unwrap();
+    std::fs::create_dir_all(prefix)?;
     let mut file = std::fs::File::create(&cache_path)?;
     let json_str = serde_json::to_string(&cache)?;
-    file.write(&json_str.as_bytes())?;
-    println!("{}", "Saved cache successfully!".green());
+    file.write_all(json_str.as_bytes())?;
     Ok(())
 }
 
-pub fn save_api_key(api_key: &str) -> anyhow::Result<()> {
-    let config_path = api_file_path();
+pub fn save_api_key(provider: Provider, api_key: &str) -> anyhow::Result<()> {
+    let config_path = api_file_path(provider)?;
 
     let prefix = config_path.parent().unwrap();
     std::fs::create_dir_all(prefix)?;
 
     let mut file = std::fs::File::create(&config_path)?;
     let encrypted = encrypt(api_key.as_bytes(), encryption_password(), nonce()?)?;
-    file.write_all(&*encrypted)
+    file.write_all(&encrypted)
         .with_context(|| format!("Could not save API key to {:?}", config_path))?;
-    println!(
+    eprintln!(
         "{}",
         format!("Saved {} API key successfully!", "encrypted".cyan()).green()
     );
@@ -149,26 +230,70 @@ pub fn save_api_key(api_key: &str) -> anyhow::Result<()> {
 }
 
 /// Reads API key from environment variables
-fn read_api_key_from_env() -> anyhow::Result<String> {
-    match std::env::var("OPENAI_API_KEY") {
+fn read_api_key_from_env(provider: Provider) -> anyhow::Result<String> {
+    let key_env = provider
+        .api_key_env()
+        .ok_or_else(|| no_api_key_error(provider))?;
+    match std::env::var(key_env) {
         Ok(key) => is_key_empty(key),
         Err(_) => Err(anyhow::anyhow!(
-            "OPENAI_API_KEY variable is not set correctly!"
+            "{} variable is not set correctly!",
+            key_env
         )),
     }
 }
 
+/// The error for attempting to use an API key with a provider that doesn't need one
+fn no_api_key_error(provider: Provider) -> anyhow::Error {
+    anyhow::anyhow!("{} does not use an API key", provider)
+}
+
+/// Gets the API base URL from the `PGPT_BASE_URL` env variable, then the config, otherwise the default
+///
+/// ### Arguments
+/// - `config` - The loaded configuration
+pub fn load_base_url(config: &ConfigJSON) -> String {
+    let base_url = match std::env::var("PGPT_BASE_URL") {
+        Ok(url) if !url.trim().is_empty() => url,
+        _ => config
+            .base_url
+            .clone()
+            .unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
+    };
+    base_url.trim().trim_end_matches('/').to_string()
+}
+
+/// Checks that a name (e.g. of a role or session) can be used as a file name
+///
+/// ### Arguments
+/// - `kind` - What is being named, for the error
+/// - `name` - The name to check
+pub fn validate_name(kind: &str, name: &str) -> Result<(), PgptError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(PgptError::InvalidArgument(format!(
+            "{} names can only contain letters, digits, `-` and `_` (got `{}`)",
+            kind, name
+        )))
+    }
+}
+
 /// Verifies key string is not empty and trims it
 fn is_key_empty(key: String) -> anyhow::Result<String> {
     if key.trim().is_empty() {
-        return Err(anyhow::anyhow!("API Key is empty!"));
+        Err(anyhow::anyhow!("API Key is empty!"))
     } else {
-        return Ok(key.trim().to_string());
+        Ok(key.trim().to_string())
     }
 }
 
 /// Gets the config directory path
-fn config_dir_path<'a>() -> std::path::PathBuf {
+fn config_dir_path() -> std::path::PathBuf {
     directories::ProjectDirs::from("com", "pgpt", "pgpt")
         .unwrap()
         .config_dir()
@@ -176,8 +301,11 @@ fn config_dir_path<'a>() -> std::path::PathBuf {
 }
 
 /// Gets the api file path
-pub fn api_file_path() -> std::path::PathBuf {
-    config_dir_path().join("key.enc")
+pub fn api_file_path(provider: Provider) -> anyhow::Result<std::path::PathBuf> {
+    let file_name = provider
+        .api_key_file()
+        .ok_or_else(|| no_api_key_error(provider))?;
+    Ok(config_dir_path().join(file_name))
 }
 
 /// Gets the config file path
@@ -185,19 +313,56 @@ pub fn config_file_path() -> std::path::PathBuf {
     config_dir_path().join("./config.json")
 }
 
-/// Gets the cache file path
-pub fn cache_file_path() -> std::path::PathBuf {
-    config_dir_path().join("./cache.json")
+/// Gets the path of the file the history of a session is saved to
+///
+/// ### Arguments
+/// - `session` - The name of the session
+pub fn cache_file_path(session: &str) -> std::path::PathBuf {
+    if session == DEFAULT_SESSION {
+        config_dir_path().join("./cache.json")
+    } else {
+        sessions_dir_path().join(format!("{}.json", session))
+    }
+}
+
+/// Gets the path of the directory the histories of the sessions other than the default are saved in
+pub fn sessions_dir_path() -> std::path::PathBuf {
+    config_dir_path().join("./sessions")
+}
+
+/// Gets the path of the file with the user's model prices
+pub fn pricing_file_path() -> std::path::PathBuf {
+    config_dir_path().join("./pricing.json")
+}
+
+/// Gets the path of the directory the prompt templates are saved in
+pub fn templates_dir_path() -> std::path::PathBuf {
+    config_dir_path().join("./templates")
+}
+
+/// Gets the path of the file the input history of `pgpt chat` is saved to
+pub fn chat_history_file_path() -> std::path::PathBuf {
+    config_dir_path().join("./chat_history.txt")
+}
+
+/// Gets the path of the file the roles are saved to
+pub fn roles_file_path() -> std::path::PathBuf {
+    config_dir_path().join("./roles.json")
+}
+
+/// Gets the path of the file the discovered models are saved to
+pub fn models_file_path() -> std::path::PathBuf {
+    config_dir_path().join("./models.json")
 }
 
 /// Gets the plaintext encryption password
 pub fn encryption_password() -> String {
-    return format!("{}_{}", whoami::username(), "pgpt_a1b2c3d4e5f6g7h8");
+    format!("{}_{}", whoami::username(), "pgpt_a1b2c3d4e5f6g7h8")
 }
 
 /// Removes the local API key file.
-pub fn clear_api_key() -> anyhow::Result<()> {
-    let config_path = api_file_path();
+pub fn clear_api_key(provider: Provider) -> anyhow::Result<()> {
+    let config_path = api_file_path(provider)?;
     std::fs::remove_file(&config_path)?;
     println!(
         "Removed config file at {}",
@@ -206,13 +371,51 @@ pub fn clear_api_key() -> anyhow::Result<()> {
     Ok(())
 }
 
-/// Clears the local cache file
-pub fn clear_cache() -> anyhow::Result<()> {
-    let cache_path = cache_file_path();
-    let mut file = std::fs::File::create(&cache_path)?;
-    let empty: Vec<CacheValue> = Vec::new();
-    let empty_str = serde_json::to_string(&empty)?;
-    file.write(&empty_str.as_bytes())?;
-    println!("{}", "Cleared cache successfully!".green());
+/// Clears the history of a session
+///
+/// ### Arguments
+/// - `session` - The name of the session
+pub fn clear_cache(session: &str) -> anyhow::Result<()> {
+    save_cache(session, Vec::new())?;
+    println!(
+        "{}",
+        format!("Cleared cache of session {} successfully!", session).green()
+    );
+    Ok(())
+}
+
+/// Resets the API base URL to the default
+pub fn clear_base_url() -> anyhow::Result<()> {
+    let mut config = load_config_file()?;
+    config.base_url = None;
+    save_config_file(&config)?;
+    println!("Reset {} to {}", "base-url".cyan(), DEFAULT_BASE_URL.cyan());
+    Ok(())
+}
+
+/// Removes the Azure OpenAI settings
+pub fn clear_azure() -> anyhow::Result<()> {
+    let mut config = load_config_file()?;
+    config.azure = None;
+    save_config_file(&config)?;
+    println!("Cleared {} settings", "Azure".cyan());
+    Ok(())
+}
+
+/// Removes the system prompt
+pub fn clear_system_prompt() -> anyhow::Result<()> {
+    let mut config = load_config_file()?;
+    config.system_prompt = None;
+    save_config_file(&config)?;
+    println!("Cleared {}", "system-prompt".cyan());
+    Ok(())
+}
+
+/// Removes the sampling defaults so the provider defaults are used
+pub fn clear_sampling() -> anyhow::Result<()> {
+    let mut config = load_config_file()?;
+    config.sampling = SamplingParams::default();
+    save_config_file(&config)?;
+    println!("Cleared {} defaults", "sampling".cyan());
     Ok(())
 }
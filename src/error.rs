@@ -0,0 +1,318 @@
+use reqwest::blocking::Response;
+use reqwest::StatusCode;
+
+/// Failures that callers (e.g. wrapper scripts) may want to react to, each with its own exit code
+#[derive(Debug, thiserror::Error)]
+pub enum PgptError {
+    #[error("Authentication failed: {0}")]
+    Authentication(String),
+    #[error("Rate limit reached: {0}")]
+    RateLimit(String),
+    #[error("Context length exceeded: {0}")]
+    ContextLengthExceeded(String),
+    #[error("Invalid model: {0}")]
+    InvalidModel(String),
+    #[error("Network error: {0}")]
+    Network(String),
+    #[error("Malformed response: {0}")]
+    MalformedResponse(String),
+    #[error("Content blocked: {0}")]
+    ContentBlocked(String),
+    /// `status` is `None` for errors reported inside a successful (e.g. streamed) response
+    #[error("API error{}: {message}", status.map(|status| format!(" ({})", status)).unwrap_or_default())]
+    Api {
+        status: Option<u16>,
+        message: String,
+    },
+    #[error("Configuration is corrupt: {0}")]
+    ConfigCorrupt(String),
+    #[error("Failed to decrypt the saved API key: {0}")]
+    KeyDecryptFailed(String),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("Response failed validation: {0}")]
+    SchemaValidation(String),
+}
+
+impl PgptError {
+    /// Returns the process exit code for the error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Authentication(_) => 10,
+            Self::RateLimit(_) => 11,
+            Self::ContextLengthExceeded(_) => 12,
+            Self::InvalidModel(_) => 13,
+            Self::Network(_) => 14,
+            Self::MalformedResponse(_) => 15,
+            Self::ContentBlocked(_) => 16,
+            Self::Api { .. } => 17,
+            Self::SchemaValidation(_) => 18,
+            Self::ConfigCorrupt(_) => 20,
+            Self::KeyDecryptFailed(_) => 21,
+            // Same as the usage errors reported by clap
+            Self::InvalidArgument(_) => 2,
+        }
+    }
+
+    /// Creates the error for an unsuccessful API response. The body does not need to be JSON.
+    ///
+    /// ### Arguments
+    /// - `response` - The unsuccessful response
+    pub fn from_response(response: Response) -> Self {
+        let status = response.status();
+        let body = response.text().unwrap_or_default();
+        Self::from_status(status, &body)
+    }
+
+    /// Classifies an API error from its status and body
+    ///
+    /// ### Arguments
+    /// - `status` - The HTTP status of the response
+    /// - `body` - The response body
+    pub fn from_status(status: StatusCode, body: &str) -> Self {
+        Self::classify(Some(status), body)
+    }
+
+    /// Classifies an error reported inside a successful response, such as an `error` event in a
+    /// stream, from its `type` or `code` and message alone
+    ///
+    /// ### Arguments
+    /// - `body` - The JSON of the error
+    pub fn from_error_body(body: &str) -> Self {
+        Self::classify(None, body)
+    }
+
+    /// Classifies an API error from its body and, for unsuccessful responses, its status
+    ///
+    /// ### Arguments
+    /// - `status` - The HTTP status of the response, if it was unsuccessful
+    /// - `body` - The response body or the error in a streamed response
+    fn classify(status: Option<StatusCode>, body: &str) -> Self {
+        let json: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        let error = &json["error"];
+        let message = match (error.as_str(), error["message"].as_str()) {
+            // Ollama returns `{"error": "..."}`
+            (Some(message), _) | (_, Some(message)) => message.to_string(),
+            _ if body.trim().is_empty() => status
+                .map(|status| status.to_string())
+                .unwrap_or_else(|| "Empty error".to_string()),
+            _ => body.trim().to_string(),
+        };
+        let code = [&error["code"], &error["type"], &error["status"]]
+            .iter()
+            .find_map(|code| code.as_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        let lower_message = message.to_lowercase();
+
+        match status {
+            Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => Self::Authentication(message),
+            Some(StatusCode::TOO_MANY_REQUESTS) => Self::RateLimit(message),
+            _ if code == "context_length_exceeded"
+                || lower_message.contains("context length")
+                || lower_message.contains("prompt is too long")
+                || lower_message.contains("exceeds the maximum number of tokens") =>
+            {
+                Self::ContextLengthExceeded(message)
+            }
+            _ if code == "model_not_found"
+                || (status == Some(StatusCode::NOT_FOUND) && lower_message.contains("model")) =>
+            {
+                Self::InvalidModel(message)
+            }
+            _ if code == "invalid_api_key"
+                || code == "authentication_error"
+                || code == "permission_error" =>
+            {
+                Self::Authentication(message)
+            }
+            _ if code == "rate_limit_error" || code == "resource_exhausted" => {
+                Self::RateLimit(message)
+            }
+            _ => Self::Api {
+                status: status.map(|status| status.as_u16()),
+                message,
+            },
+        }
+    }
+}
+
+impl From<reqwest::Error> for PgptError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            Self::MalformedResponse(e.to_string())
+        } else {
+            Self::Network(e.to_string())
+        }
+    }
+}
+
+impl From<std::io::Error> for PgptError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Network(e.to_string())
+    }
+}
+
+/// Parses a JSON response body
+///
+/// ### Arguments
+/// - `response` - The successful response to parse
+pub fn parse_json<T: serde::de::DeserializeOwned>(response: Response) -> Result<T, PgptError> {
+    let body = response.text()?;
+    serde_json::from_str(&body)
+        .map_err(|e| PgptError::MalformedResponse(format!("{}: {}", e, body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statuses_and_bodies_map_to_exit_codes() {
+        let openai = |code: &str, message: &str| {
+            format!(
+                r#"{{"error":{{"message":"{}","type":"invalid_request_error","code":"{}"}}}}"#,
+                message, code
+            )
+        };
+        let anthropic = |error_type: &str, message: &str| {
+            format!(
+                r#"{{"type":"error","error":{{"type":"{}","message":"{}"}}}}"#,
+                error_type, message
+            )
+        };
+        let cases = [
+            (401, openai("invalid_api_key", "Incorrect API key"), 10),
+            (403, anthropic("permission_error", "No access"), 10),
+            (400, openai("invalid_api_key", "Incorrect API key"), 10),
+            (429, openai("rate_limit_exceeded", "Slow down"), 11),
+            (400, anthropic("rate_limit_error", "Slow down"), 11),
+            (
+                400,
+                openai("context_length_exceeded", "Too many tokens"),
+                12,
+            ),
+            (
+                400,
+                anthropic("invalid_request_error", "prompt is too long: 300000 tokens"),
+                12,
+            ),
+            (
+                400,
+                String::from(
+                    r#"{"error":{"code":400,"message":"The input token count exceeds the maximum number of tokens allowed","status":"INVALID_ARGUMENT"}}"#,
+                ),
+                12,
+            ),
+            (
+                404,
+                openai("model_not_found", "The model does not exist"),
+                13,
+            ),
+            (
+                404,
+                String::from(r#"{"error":"model 'llama9' not found"}"#),
+                13,
+            ),
+            (404, String::from("Not Found"), 17),
+            (400, anthropic("invalid_request_error", "Bad request"), 17),
+            (500, anthropic("api_error", "Internal error"), 17),
+            (503, String::new(), 17),
+            (529, anthropic("overloaded_error", "Overloaded"), 17),
+        ];
+
+        for (status, body, exit_code) in cases {
+            let error = PgptError::from_status(StatusCode::from_u16(status).unwrap(), &body);
+            assert_eq!(
+                error.exit_code(),
+                exit_code,
+                "{} {}: {:?}",
+                status,
+                body,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn messages_are_taken_from_the_body() {
+        let error = PgptError::from_status(
+            StatusCode::UNAUTHORIZED,
+            r#"{"error":{"message":"Incorrect API key"}}"#,
+        );
+        assert_eq!(
+            error.to_string(),
+            "Authentication failed: Incorrect API key"
+        );
+
+        let error = PgptError::from_status(StatusCode::BAD_GATEWAY, "  <html>Bad gateway</html>\n");
+        assert_eq!(
+            error.to_string(),
+            "API error (502): <html>Bad gateway</html>"
+        );
+
+        let error = PgptError::from_status(StatusCode::SERVICE_UNAVAILABLE, "");
+        assert_eq!(
+            error.to_string(),
+            "API error (503): 503 Service Unavailable"
+        );
+    }
+
+    #[test]
+    fn error_bodies_are_classified_without_a_status() {
+        let cases = [
+            (
+                r#"{"error":{"type":"authentication_error","message":"Bad key"}}"#,
+                10,
+            ),
+            (
+                r#"{"error":{"type":"rate_limit_error","message":"Slow down"}}"#,
+                11,
+            ),
+            (r#"{"error":"the prompt exceeds the context length"}"#, 12),
+            (
+                r#"{"error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+                17,
+            ),
+        ];
+        for (body, exit_code) in cases {
+            assert_eq!(
+                PgptError::from_error_body(body).exit_code(),
+                exit_code,
+                "{}",
+                body
+            );
+        }
+
+        let error = PgptError::from_error_body("");
+        assert!(matches!(error, PgptError::Api { status: None, .. }));
+        assert_eq!(error.to_string(), "API error: Empty error");
+    }
+
+    #[test]
+    fn network_errors_have_their_own_exit_code() {
+        let io_error = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset");
+        assert_eq!(PgptError::from(io_error).exit_code(), 14);
+
+        let request_error = reqwest::blocking::Client::new()
+            .get("not a url")
+            .send()
+            .unwrap_err();
+        assert_eq!(PgptError::from(request_error).exit_code(), 14);
+    }
+
+    #[test]
+    fn local_errors_have_their_own_exit_codes() {
+        let cases = [
+            (PgptError::MalformedResponse(String::new()), 15),
+            (PgptError::ContentBlocked(String::new()), 16),
+            (PgptError::SchemaValidation(String::new()), 18),
+            (PgptError::ConfigCorrupt(String::new()), 20),
+            (PgptError::KeyDecryptFailed(String::new()), 21),
+            (PgptError::InvalidArgument(String::new()), 2),
+        ];
+        for (error, exit_code) in cases {
+            assert_eq!(error.exit_code(), exit_code, "{:?}", error);
+        }
+    }
+}
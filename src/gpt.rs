@@ -1,9 +1,20 @@
 use crate::config::model::Model;
+use crate::config::pricing::PricingTable;
+use crate::error::{parse_json, PgptError};
+use crate::provider::retry::{self, RetryPolicy};
+use crate::provider::{sse_data, ChatProvider, ChatRequest, ChatResponse, ResponseFormat};
+use base64::Engine;
 use reqwest::blocking::{Client, Response};
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE, USER_AGENT};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
 
-const COMPLETION_URL: &'static str = "https://api.openai.com/v1/chat/completions";
+/// The base URL of the OpenAI API
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// The largest image that can be attached to a query
+const MAX_IMAGE_BYTES: u64 = 20 * 1024 * 1024;
 
 #[derive(Deserialize, Debug)]
 #[allow(unused)]
@@ -22,24 +33,145 @@ pub struct GPTChoice {
     pub finish_reason: String,
     pub index: i32,
     pub message: GPTMessage,
+    pub logprobs: Option<GPTLogprobs>,
+}
+
+/// The log probabilities of the tokens in a choice
+#[derive(Deserialize, Debug, Default)]
+pub struct GPTLogprobs {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub content: Vec<GPTTokenLogprob>,
+}
+
+/// A generated token and how likely the model thought it was
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GPTTokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+    /// The most likely tokens at this position, when `top_logprobs` was requested
+    #[serde(default)]
+    pub top_logprobs: Vec<GPTTopLogprob>,
+}
+
+/// One of the most likely tokens at a position
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GPTTopLogprob {
+    pub token: String,
+    pub logprob: f64,
+}
+
+impl GPTTokenLogprob {
+    /// Returns the probability (0-1) of the token
+    pub fn probability(&self) -> f64 {
+        self.logprob.exp()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+pub struct GPTStreamChunk {
+    pub choices: Vec<GPTStreamChoice>,
+    pub created: i64,
+    pub id: String,
+    pub model: String,
+    pub usage: Option<GPTUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+pub struct GPTStreamChoice {
+    pub finish_reason: Option<String>,
+    pub index: i32,
+    pub delta: GPTDelta,
+    pub logprobs: Option<GPTLogprobs>,
 }
 
 #[derive(Deserialize, Debug)]
 #[allow(unused)]
+pub struct GPTDelta {
+    pub role: Option<GPTRole>,
+    pub content: Option<String>,
+    pub tool_calls: Option<Vec<GPTToolCallDelta>>,
+}
+
+/// A piece of a tool call from a streamed response. The `id` and function name arrive in the first
+/// piece and the arguments are split across the rest.
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+pub struct GPTToolCallDelta {
+    pub index: usize,
+    pub id: Option<String>,
+    pub function: Option<GPTFunctionCallDelta>,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+pub struct GPTFunctionCallDelta {
+    pub name: Option<String>,
+    pub arguments: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[allow(unused)]
 pub struct GPTUsage {
     pub completion_tokens: i32,
     pub prompt_tokens: i32,
     pub total_tokens: i32,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub prompt_tokens_details: GPTPromptTokensDetails,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub completion_tokens_details: GPTCompletionTokensDetails,
+}
+
+/// The breakdown of the prompt tokens
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct GPTPromptTokensDetails {
+    /// Prompt tokens read from the provider's prompt cache (included in `prompt_tokens`)
+    #[serde(default)]
+    pub cached_tokens: i32,
+    /// Prompt tokens written to the provider's prompt cache (included in `prompt_tokens`)
+    #[serde(default)]
+    pub cache_write_tokens: i32,
+}
+
+/// The breakdown of the completion tokens
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct GPTCompletionTokensDetails {
+    /// Tokens the model spent reasoning (included in `completion_tokens`)
+    #[serde(default)]
+    pub reasoning_tokens: i32,
 }
 
 impl GPTUsage {
-    /// Calculates the total cost of the query
+    /// Calculates the total cost of the query, or `None` if the model has no price
     ///
     /// ### Arguments
     /// - `model` - The model used for the query (*cost is dependent on model*)
-    pub fn total_cost(&self, model: &Model) -> f64 {
-        ((self.prompt_tokens as f64) * model.prompt_cost())
-            + ((self.completion_tokens as f64) * model.completion_cost())
+    /// - `pricing` - The prices of the models
+    pub fn total_cost(&self, model: &Model, pricing: &PricingTable) -> Option<f64> {
+        let price = pricing.get(model)?;
+        let cached = self.prompt_tokens_details.cached_tokens;
+        let cache_write = self.prompt_tokens_details.cache_write_tokens;
+        let reasoning = self.completion_tokens_details.reasoning_tokens;
+        let cost = ((self.prompt_tokens - cached - cache_write) as f64) * price.input
+            + (cached as f64) * price.cached_input()
+            + (cache_write as f64) * price.cache_write()
+            + ((self.completion_tokens - reasoning) as f64) * price.output
+            + (reasoning as f64) * price.reasoning();
+        Some(cost / 1e6)
+    }
+}
+
+impl std::ops::AddAssign<&GPTUsage> for GPTUsage {
+    fn add_assign(&mut self, other: &GPTUsage) {
+        self.completion_tokens += other.completion_tokens;
+        self.prompt_tokens += other.prompt_tokens;
+        self.total_tokens += other.total_tokens;
+        self.prompt_tokens_details.cached_tokens += other.prompt_tokens_details.cached_tokens;
+        self.prompt_tokens_details.cache_write_tokens +=
+            other.prompt_tokens_details.cache_write_tokens;
+        self.completion_tokens_details.reasoning_tokens +=
+            other.completion_tokens_details.reasoning_tokens;
     }
 }
 
@@ -49,121 +181,673 @@ pub enum GPTRole {
     System,
     Assistant,
     User,
+    /// The result of a tool call, sent back to the model
+    Tool,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct GPTMessage {
     pub role: GPTRole,
-    pub content: String,
+    /// The content of the message (`null` in assistant messages that only contain tool calls)
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub content: GPTContent,
+    /// The tools the assistant asked to call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<GPTToolCall>>,
+    /// The tool call a `tool` message is the result of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
-pub struct GPTQuery {
-    pub model: String,
-    pub messages: Vec<GPTMessage>,
+impl GPTMessage {
+    /// Creates a plain text message
+    ///
+    /// ### Arguments
+    /// - `role` - The role of the message (e.g. "user")
+    /// - `content` - The content of the message
+    pub fn new(role: GPTRole, content: &str) -> Self {
+        Self {
+            role,
+            content: GPTContent::Text(content.to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    /// Creates a message with text followed by images
+    ///
+    /// ### Arguments
+    /// - `role` - The role of the message (e.g. "user")
+    /// - `content` - The text of the message
+    /// - `images` - The images to attach
+    pub fn with_images(role: GPTRole, content: &str, images: &[GPTImageUrl]) -> Self {
+        let mut parts = vec![GPTContentPart::Text {
+            text: content.to_string(),
+        }];
+        parts.extend(images.iter().map(|image| GPTContentPart::ImageUrl {
+            image_url: image.clone(),
+        }));
+        Self {
+            role,
+            content: GPTContent::Parts(parts),
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    /// Creates the message that sends the result of a tool call back to the model
+    ///
+    /// ### Arguments
+    /// - `tool_call_id` - The ID of the tool call
+    /// - `content` - The output of the tool
+    pub fn tool_result(tool_call_id: &str, content: &str) -> Self {
+        Self {
+            role: GPTRole::Tool,
+            content: GPTContent::Text(content.to_string()),
+            tool_calls: None,
+            tool_call_id: Some(tool_call_id.to_string()),
+        }
+    }
 }
 
-pub struct GPTQueryBuilder {
-    pub model: Option<Model>,
-    pub messages: Vec<GPTMessage>,
+/// Deserializes a value that may be `null` as its default
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
 }
 
-impl GPTQueryBuilder {
-    pub fn new() -> Self {
-        return Self {
-            model: None,
-            messages: Vec::new(),
-        };
+/// The content of a message, either plain text or a list of text and image parts
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum GPTContent {
+    Text(String),
+    Parts(Vec<GPTContentPart>),
+}
+
+impl Default for GPTContent {
+    fn default() -> Self {
+        Self::Text(String::new())
+    }
+}
+
+impl GPTContent {
+    /// Gets the text of the content, joining the text parts
+    pub fn text(&self) -> String {
+        match self {
+            Self::Text(text) => text.clone(),
+            Self::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    GPTContentPart::Text { text } => Some(text.as_str()),
+                    GPTContentPart::ImageUrl { .. } => None,
+                })
+                .collect::<Vec<&str>>()
+                .join("\n"),
+        }
     }
 
-    /// Sets the model
-    ///
-    /// ### Arguments
-    /// - `model` - The model to use in the query
-    pub fn model(&mut self, model: &Model) -> &mut Self {
-        self.model = Some(model.clone());
-        self
+    /// Gets the images attached to the content
+    pub fn images(&self) -> Vec<&GPTImageUrl> {
+        match self {
+            Self::Text(_) => Vec::new(),
+            Self::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    GPTContentPart::ImageUrl { image_url } => Some(image_url),
+                    GPTContentPart::Text { .. } => None,
+                })
+                .collect(),
+        }
     }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GPTContentPart {
+    Text { text: String },
+    ImageUrl { image_url: GPTImageUrl },
+}
 
-    /// Adds a message to the query
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GPTImageUrl {
+    /// A web URL or a base64 `data:` URL
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl GPTImageUrl {
+    /// Reads an image file into a base64 data URL
     ///
     /// ### Arguments
-    /// - `role` - The role of the message (e.g. "user")
-    /// - `content` - The content of the message
-    pub fn message(&mut self, role: GPTRole, content: &str) -> &mut Self {
-        let message = GPTMessage {
-            role,
-            content: content.to_string(),
+    /// - `path` - The path of the image (PNG, JPEG, GIF or WebP)
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+        let media_type = match extension.as_deref() {
+            Some("png") => "image/png",
+            Some("jpg") | Some("jpeg") => "image/jpeg",
+            Some("gif") => "image/gif",
+            Some("webp") => "image/webp",
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Unsupported image type {:?}, expected png, jpeg, gif or webp",
+                    path
+                ))
+            }
         };
-        self.messages.push(message);
-        self
+
+        let size = std::fs::metadata(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read image {:?}: {}", path, e))?
+            .len();
+        if size > MAX_IMAGE_BYTES {
+            return Err(anyhow::anyhow!(
+                "Image {:?} is {} bytes, larger than the {} byte limit",
+                path,
+                size,
+                MAX_IMAGE_BYTES
+            ));
+        }
+        let bytes = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read image {:?}: {}", path, e))?;
+        let data = base64::engine::general_purpose::STANDARD.encode(bytes);
+        Ok(Self {
+            url: format!("data:{};base64,{}", media_type, data),
+            detail: None,
+        })
     }
 
-    /// Builds the query
-    pub fn build(&self) -> anyhow::Result<GPTQuery> {
-        if self.model.is_none() {
-            return Err(anyhow::anyhow!("Cannot build query without model!"));
+    /// Splits a base64 data URL into its media type and data, or `None` for web URLs
+    pub fn base64_data(&self) -> Option<(&str, &str)> {
+        self.url.strip_prefix("data:")?.split_once(";base64,")
+    }
+}
+
+/// A tool the model may call
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GPTTool {
+    /// Always `function`
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: GPTFunction,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GPTFunction {
+    pub name: String,
+    pub description: String,
+    /// The JSON Schema of the function arguments
+    pub parameters: serde_json::Value,
+}
+
+impl GPTTool {
+    /// Creates a function tool
+    ///
+    /// ### Arguments
+    /// - `name` - The name the model uses to call the function
+    /// - `description` - What the function does and when to use it
+    /// - `parameters` - The JSON Schema of the function arguments
+    pub fn function(name: &str, description: &str, parameters: serde_json::Value) -> Self {
+        Self {
+            tool_type: String::from("function"),
+            function: GPTFunction {
+                name: name.to_string(),
+                description: description.to_string(),
+                parameters,
+            },
         }
+    }
+}
+
+/// Controls whether and which tools the model calls
+#[derive(Debug, Clone)]
+pub enum GPTToolChoice {
+    /// Never call a tool
+    None,
+    /// Let the model decide (the default when tools are sent)
+    Auto,
+    /// Call at least one tool
+    Required,
+    /// Call the function with this name
+    Function(String),
+}
 
-        if self.messages.is_empty() {
-            return Err(anyhow::anyhow!("Cannot build query with no messages!"));
+impl Serialize for GPTToolChoice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::None => serializer.serialize_str("none"),
+            Self::Auto => serializer.serialize_str("auto"),
+            Self::Required => serializer.serialize_str("required"),
+            Self::Function(name) => serde_json::json!({
+                "type": "function",
+                "function": { "name": name }
+            })
+            .serialize(serializer),
         }
+    }
+}
 
-        let model = self.model.clone().unwrap();
-        let query = GPTQuery {
-            model: model.api_model(),
-            messages: self.messages.clone(),
-        };
-        Ok(query)
+/// A call the model made to one of the tools
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GPTToolCall {
+    pub id: String,
+    /// Always `function`
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: GPTFunctionCall,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct GPTFunctionCall {
+    pub name: String,
+    /// The arguments as a JSON string. The model may produce invalid JSON.
+    pub arguments: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GPTQuery {
+    pub model: String,
+    pub messages: Vec<GPTMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub logprobs: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_logprobs: Option<u32>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<GPTStreamOptions>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<GPTTool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<GPTToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<GPTResponseFormat>,
+}
+
+/// Forces the response to be JSON
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GPTResponseFormat {
+    JsonObject,
+    JsonSchema { json_schema: GPTJsonSchema },
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GPTJsonSchema {
+    pub name: String,
+    pub schema: serde_json::Value,
+}
+
+impl From<&ResponseFormat> for GPTResponseFormat {
+    fn from(format: &ResponseFormat) -> Self {
+        match format {
+            ResponseFormat::JsonObject => Self::JsonObject,
+            ResponseFormat::JsonSchema { name, schema } => Self::JsonSchema {
+                json_schema: GPTJsonSchema {
+                    name: name.clone(),
+                    schema: schema.clone(),
+                },
+            },
+        }
     }
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GPTStreamOptions {
+    pub include_usage: bool,
+}
+
 impl GPTQuery {
-    pub fn builder() -> GPTQueryBuilder {
-        GPTQueryBuilder::new()
+    /// Creates the OpenAI query for a provider-neutral request
+    ///
+    /// ### Arguments
+    /// - `request` - The request to convert
+    pub fn from_request(request: &ChatRequest) -> Self {
+        Self {
+            model: request.model.api_model(),
+            messages: request.messages.clone(),
+            temperature: request.params.temperature,
+            top_p: request.params.top_p,
+            max_tokens: request.params.max_tokens,
+            seed: request.params.seed,
+            stop: request.params.stop.clone(),
+            presence_penalty: request.params.presence_penalty,
+            frequency_penalty: request.params.frequency_penalty,
+            n: (request.choices > 1).then_some(request.choices),
+            logprobs: request.logprobs.is_some(),
+            top_logprobs: request.logprobs.filter(|top| *top > 0),
+            stream: false,
+            stream_options: None,
+            tools: request.tools.clone(),
+            tool_choice: request.tool_choice.clone(),
+            response_format: request
+                .response_format
+                .as_ref()
+                .map(GPTResponseFormat::from),
+        }
     }
 }
 
+/// The models offered by an OpenAI-compatible API
+#[derive(Deserialize, Debug)]
+pub struct GPTModelList {
+    pub data: Vec<GPTModelInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GPTModelInfo {
+    pub id: String,
+}
+
 pub struct GPTClient {
     http_client: Client,
+    completion_url: String,
+    /// Where the available models are listed (Azure OpenAI lists deployments instead)
+    models_url: Option<String>,
+    retry_policy: RetryPolicy,
+}
+
+/// How requests to the API are authorized
+enum GPTAuth<'a> {
+    /// `Authorization: Bearer <key>` (OpenAI and most compatible servers)
+    Bearer(&'a str),
+    /// `api-key: <key>` (Azure OpenAI)
+    APIKeyHeader(&'a str),
 }
 
 impl GPTClient {
-    fn create_http_client(api_key: &str) -> anyhow::Result<Client> {
-        let auth_val = format!("Bearer {}", api_key);
+    fn create_http_client(auth: GPTAuth) -> anyhow::Result<Client> {
         let user_agent = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
         let mut headers = HeaderMap::new();
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
         headers.insert(USER_AGENT, HeaderValue::from_str(&user_agent)?);
-        headers.insert(AUTHORIZATION, HeaderValue::from_str(&auth_val)?);
+        match auth {
+            GPTAuth::Bearer(api_key) => {
+                let auth_val = format!("Bearer {}", api_key);
+                headers.insert(AUTHORIZATION, HeaderValue::from_str(&auth_val)?);
+            }
+            GPTAuth::APIKeyHeader(api_key) => {
+                headers.insert("api-key", HeaderValue::from_str(api_key)?);
+            }
+        }
         let client = Client::builder().default_headers(headers).build()?;
         Ok(client)
     }
 
-    pub fn new(api_key: &str) -> anyhow::Result<Self> {
-        let http_client = Self::create_http_client(api_key)?;
-        let gpt = Self { http_client };
+    /// Creates a client for an OpenAI-compatible API
+    ///
+    /// ### Arguments
+    /// - `api_key` - The API key used to authorize requests
+    /// - `base_url` - The base URL of the API (e.g. `https://api.openai.com/v1`)
+    pub fn new(api_key: &str, base_url: &str) -> anyhow::Result<Self> {
+        let http_client = Self::create_http_client(GPTAuth::Bearer(api_key))?;
+        let completion_url = format!("{}/chat/completions", base_url.trim_end_matches('/'));
+        let models_url = format!("{}/models", base_url.trim_end_matches('/'));
+        let gpt = Self {
+            http_client,
+            completion_url,
+            models_url: Some(models_url),
+            retry_policy: RetryPolicy::default(),
+        };
+        Ok(gpt)
+    }
+
+    /// Creates a client for an Azure OpenAI deployment
+    ///
+    /// ### Arguments
+    /// - `api_key` - The Azure OpenAI API key used to authorize requests
+    /// - `endpoint` - The resource endpoint (e.g. `https://my-resource.openai.azure.com`)
+    /// - `deployment` - The name of the model deployment
+    /// - `api_version` - The Azure OpenAI API version (e.g. `2024-10-21`)
+    pub fn new_azure(
+        api_key: &str,
+        endpoint: &str,
+        deployment: &str,
+        api_version: &str,
+    ) -> anyhow::Result<Self> {
+        let http_client = Self::create_http_client(GPTAuth::APIKeyHeader(api_key))?;
+        let completion_url = format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            endpoint.trim_end_matches('/'),
+            deployment,
+            api_version
+        );
+        let gpt = Self {
+            http_client,
+            completion_url,
+            models_url: None,
+            retry_policy: RetryPolicy::default(),
+        };
         Ok(gpt)
     }
 
+    /// Sets how failed requests are retried
+    ///
+    /// ### Arguments
+    /// - `retry_policy` - The retry policy to use
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
     /// Queries ChatGPT
     ///
     /// ### Arguments
     /// - `query` - The query to send to ChatGPT
     pub fn query(&self, gpt_query: &GPTQuery) -> anyhow::Result<GPTResponse> {
-        let response: Response = self
-            .http_client
-            .post(COMPLETION_URL)
-            .json(gpt_query)
-            .send()?;
+        let response = self.send(gpt_query)?;
+        let gpt_response: GPTResponse = parse_json(response)?;
+        Ok(gpt_response)
+    }
+
+    /// Queries ChatGPT with streaming enabled, calling `on_token` with each piece of content as it arrives.
+    /// The streamed pieces are assembled into a full response (including usage) once the stream ends.
+    ///
+    /// ### Arguments
+    /// - `query` - The query to send to ChatGPT
+    /// - `on_token` - Called with each content delta in the order they are received
+    pub fn query_stream(
+        &self,
+        gpt_query: &GPTQuery,
+        mut on_token: impl FnMut(&str),
+    ) -> anyhow::Result<GPTResponse> {
+        let mut stream_query = gpt_query.clone();
+        stream_query.stream = true;
+        stream_query.stream_options = Some(GPTStreamOptions {
+            include_usage: true,
+        });
+        let response = self.send(&stream_query)?;
+
+        let mut content = String::new();
+        let mut tool_calls: Vec<GPTToolCall> = Vec::new();
+        let mut finish_reason = String::new();
+        let mut logprobs: Option<GPTLogprobs> = None;
+        let mut gpt_response = GPTResponse {
+            choices: Vec::new(),
+            created: 0,
+            id: String::new(),
+            model: gpt_query.model.clone(),
+            object: String::from("chat.completion"),
+            usage: GPTUsage::default(),
+        };
+
+        for line in BufReader::new(response).lines() {
+            let line = line.map_err(PgptError::from)?;
+            let data = match sse_data(&line) {
+                Some(data) => data,
+                None => continue,
+            };
+            if data == "[DONE]" {
+                break;
+            }
+
+            let chunk: GPTStreamChunk = serde_json::from_str(data)
+                .map_err(|e| PgptError::MalformedResponse(format!("{}: {}", e, data)))?;
+            gpt_response.id = chunk.id;
+            gpt_response.model = chunk.model;
+            gpt_response.created = chunk.created;
+            if let Some(usage) = chunk.usage {
+                gpt_response.usage = usage;
+            }
+            for choice in chunk.choices.iter().filter(|choice| choice.index == 0) {
+                if let Some(token) = &choice.delta.content {
+                    content.push_str(token);
+                    on_token(token);
+                }
+                for delta in choice.delta.tool_calls.iter().flatten() {
+                    if delta.index >= tool_calls.len() {
+                        tool_calls.resize_with(delta.index + 1, || GPTToolCall {
+                            id: String::new(),
+                            tool_type: String::from("function"),
+                            function: GPTFunctionCall::default(),
+                        });
+                    }
+                    let tool_call = &mut tool_calls[delta.index];
+                    if let Some(id) = &delta.id {
+                        tool_call.id.push_str(id);
+                    }
+                    if let Some(function) = &delta.function {
+                        if let Some(name) = &function.name {
+                            tool_call.function.name.push_str(name);
+                        }
+                        if let Some(arguments) = &function.arguments {
+                            tool_call.function.arguments.push_str(arguments);
+                        }
+                    }
+                }
+                if let Some(reason) = &choice.finish_reason {
+                    finish_reason = reason.clone();
+                }
+                if let Some(chunk_logprobs) = &choice.logprobs {
+                    logprobs
+                        .get_or_insert_with(GPTLogprobs::default)
+                        .content
+                        .extend(chunk_logprobs.content.iter().cloned());
+                }
+            }
+        }
+
+        gpt_response.choices.push(GPTChoice {
+            finish_reason,
+            index: 0,
+            message: GPTMessage {
+                role: GPTRole::Assistant,
+                content: GPTContent::Text(content),
+                tool_calls: if tool_calls.is_empty() {
+                    None
+                } else {
+                    Some(tool_calls)
+                },
+                tool_call_id: None,
+            },
+            logprobs,
+        });
+        Ok(gpt_response)
+    }
+
+    /// Lists the IDs of the models the API offers
+    pub fn models(&self) -> anyhow::Result<Vec<String>> {
+        let models_url = self.models_url.as_ref().ok_or_else(|| {
+            anyhow::anyhow!(
+                "Azure OpenAI models are deployments, set them with `pgpt config set azure-deployment`"
+            )
+        })?;
+        let response: Response = retry::send(self.http_client.get(models_url), &self.retry_policy)?;
+        if !response.status().is_success() {
+            return Err(PgptError::from_response(response).into());
+        }
+        let list: GPTModelList = parse_json(response)?;
+        Ok(list.data.into_iter().map(|model| model.id).collect())
+    }
+
+    /// Sends the query, converting error responses into errors
+    fn send(&self, gpt_query: &GPTQuery) -> anyhow::Result<Response> {
+        let response: Response = retry::send(
+            self.http_client.post(&self.completion_url).json(gpt_query),
+            &self.retry_policy,
+        )?;
 
         if response.status().is_success() {
-            let gpt_response: GPTResponse = response.json()?;
-            return Ok(gpt_response);
+            Ok(response)
         } else {
-            let mut err: serde_json::Value = response.json()?;
-            let message = err["error"]["message"].take();
-            return Err(anyhow::anyhow!(message));
+            Err(PgptError::from_response(response).into())
+        }
+    }
+}
+
+impl From<GPTResponse> for ChatResponse {
+    fn from(response: GPTResponse) -> Self {
+        let mut choices = response.choices.into_iter();
+        let choice = choices.next();
+        Self {
+            model: response.model,
+            text: choice
+                .as_ref()
+                .map(|choice| choice.message.content.text())
+                .unwrap_or_default(),
+            usage: response.usage,
+            tool_calls: choice
+                .as_ref()
+                .and_then(|choice| choice.message.tool_calls.clone())
+                .unwrap_or_default(),
+            logprobs: choice
+                .as_ref()
+                .and_then(|choice| choice.logprobs.as_ref())
+                .map(|logprobs| logprobs.content.clone())
+                .unwrap_or_default(),
+            finish_reason: choice.map(|choice| choice.finish_reason),
+            alternatives: choices
+                .map(|choice| choice.message.content.text())
+                .collect(),
         }
     }
 }
+
+impl ChatProvider for GPTClient {
+    fn supports_choices(&self) -> bool {
+        true
+    }
+
+    fn supports_logprobs(&self) -> bool {
+        true
+    }
+
+    fn list_models(&self) -> anyhow::Result<Vec<String>> {
+        self.models()
+    }
+
+    fn chat(&self, request: &ChatRequest) -> anyhow::Result<ChatResponse> {
+        let response = self.query(&GPTQuery::from_request(request))?;
+        Ok(response.into())
+    }
+
+    fn chat_stream(
+        &self,
+        request: &ChatRequest,
+        on_token: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<ChatResponse> {
+        let response = self.query_stream(&GPTQuery::from_request(request), on_token)?;
+        Ok(response.into())
+    }
+}
@@ -1,20 +1,34 @@
+pub mod attachment;
+pub mod chat;
 pub mod config;
 pub mod encryption;
+pub mod error;
 pub mod gpt;
+pub mod provider;
+pub mod render;
+pub mod tools;
 
 use anyhow::Context;
 use colored::*;
+use config::model::{Model, ModelCapabilities, Provider};
 use config::CacheValue;
-use gpt::{GPTClient, GPTQuery, GPTResponse, GPTRole};
+use error::PgptError;
+use gpt::{GPTImageUrl, GPTMessage, GPTRole, GPTUsage};
 use indicatif::{ProgressBar, ProgressStyle};
+use provider::{ChatProvider, ChatRequest, ChatResponse, ResponseFormat, SamplingParams};
 use rand::Rng;
+use render::{LogprobsDisplay, MarkdownStream};
 use std::collections::VecDeque;
+use std::io::{IsTerminal, Write};
 use std::sync::Arc;
-use std::thread::JoinHandle;
 use termimad::crossterm::style::Color::*;
 use termimad::{rgb, MadSkin};
+use tools::ToolRegistry;
 
-const LOADING_MESSAGES: [&'static str; 10] = [
+/// The most times the model can call tools before pgpt gives up on getting a final answer
+const MAX_TOOL_ROUNDS: usize = 10;
+
+const LOADING_MESSAGES: [&str; 10] = [
     "Consulting neural network...",
     "Bribing data set...",
     "Thinking hard...",
@@ -34,7 +48,7 @@ fn create_spinner() -> anyhow::Result<ProgressBar> {
         ProgressStyle::default_spinner()
             .tick_chars("🌍🌎🌏")
             .template("{msg} {spinner:.green}")
-            .with_context(|| format!("Failed to set template"))?,
+            .context("Failed to set template")?,
     );
     let mut rng = rand::thread_rng();
     let rand_idx = rng.gen_range(0..LOADING_MESSAGES.len());
@@ -43,12 +57,283 @@ fn create_spinner() -> anyhow::Result<ProgressBar> {
     Ok(spinner)
 }
 
+/// Sends the request and waits for all of its choices. Providers that cannot generate several
+/// choices at once are sent the request once per choice.
+///
+/// ### Arguments
+/// - `provider` - The provider to send the request to
+/// - `request` - The request to send
+fn chat_choices(
+    provider: &dyn ChatProvider,
+    request: &ChatRequest,
+) -> anyhow::Result<ChatResponse> {
+    if request.choices <= 1 || provider.supports_choices() {
+        return provider.chat(request);
+    }
+
+    let mut single = request.clone();
+    single.choices = 1;
+    let mut response = provider.chat(&single)?;
+    for _ in 1..request.choices {
+        let next = provider.chat(&single)?;
+        response.usage += &next.usage;
+        response.alternatives.push(next.text);
+    }
+    Ok(response)
+}
+
+/// Checks that the model supports everything the request asks for and that the prompt fits in its
+/// context window. What is unknown for the model is left to the provider.
+///
+/// ### Arguments
+/// - `model` - The model the request will be sent to
+/// - `capabilities` - What the model supports
+/// - `request` - The request to check
+fn check_capabilities(
+    model: &config::model::Model,
+    capabilities: &ModelCapabilities,
+    request: &ChatRequest,
+) -> Result<(), PgptError> {
+    let unsupported = |feature: &str| {
+        Err(PgptError::InvalidArgument(format!(
+            "{} does not support {}",
+            model, feature
+        )))
+    };
+    let has_images = request
+        .messages
+        .iter()
+        .any(|message| !message.content.images().is_empty());
+    if has_images && capabilities.vision == Some(false) {
+        return unsupported("images, use a vision model such as gpt-4o");
+    }
+    if !request.tools.is_empty() && capabilities.tools == Some(false) {
+        return unsupported("tool calling in pgpt");
+    }
+    match &request.response_format {
+        Some(ResponseFormat::JsonObject) if capabilities.json_mode == Some(false) => {
+            return unsupported("JSON output")
+        }
+        Some(ResponseFormat::JsonSchema { .. }) if capabilities.json_schema == Some(false) => {
+            return unsupported("JSON Schema output")
+        }
+        _ => {}
+    }
+    let has_system = request
+        .messages
+        .iter()
+        .any(|message| matches!(message.role, GPTRole::System));
+    if has_system && capabilities.system_role == Some(false) {
+        return unsupported("system messages");
+    }
+
+    let max_tokens = request.params.max_tokens.unwrap_or(0);
+    if let Some(max_output_tokens) = capabilities.max_output_tokens {
+        if max_tokens > max_output_tokens {
+            return Err(PgptError::InvalidArgument(format!(
+                "max-tokens is {} but {} can generate at most {} tokens",
+                max_tokens, model, max_output_tokens
+            )));
+        }
+    }
+    if let Some(context_window) = capabilities.context_window {
+        let prompt_tokens = request.estimated_prompt_tokens();
+        if prompt_tokens + max_tokens > context_window {
+            return Err(PgptError::ContextLengthExceeded(format!(
+                "the prompt is about {} tokens{}, more than the {} token context window of {}. Use a smaller --context or attach less",
+                prompt_tokens,
+                if max_tokens > 0 {
+                    format!(" plus {} max-tokens", max_tokens)
+                } else {
+                    String::new()
+                },
+                context_window,
+                model
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Asks which choice to save to the cache, defaulting to the first one when stdin is not a terminal
+///
+/// ### Arguments
+/// - `count` - The number of choices
+fn ask_choice(count: usize) -> anyhow::Result<usize> {
+    if !std::io::stdin().is_terminal() {
+        eprintln!(
+            "{}",
+            "Saving choice 1 to the cache, use --pick to save another one".yellow()
+        );
+        return Ok(1);
+    }
+
+    loop {
+        print!(
+            "{} ",
+            format!("Pick a choice to save to the cache [1-{}]:", count).bright_cyan()
+        );
+        std::io::stdout().flush()?;
+        let mut input = String::new();
+        if std::io::stdin()
+            .read_line(&mut input)
+            .context("Failed to read input")?
+            == 0
+        {
+            return Ok(1);
+        }
+        match input.trim() {
+            "" => return Ok(1),
+            choice => match choice.parse::<usize>() {
+                Ok(choice) if (1..=count).contains(&choice) => return Ok(choice),
+                _ => println!("{}", format!("Enter a number from 1 to {}", count).red()),
+            },
+        }
+    }
+}
+
+/// The model, sampling parameters and system prompt of a query. The command line takes precedence
+/// over the role, which takes precedence over the configuration.
+struct QuerySettings {
+    model: config::model::Model,
+    params: SamplingParams,
+    system_prompt: Option<String>,
+}
+
+impl QuerySettings {
+    /// Resolves the settings of a query, checking the sampling parameters against the provider
+    ///
+    /// ### Arguments
+    /// - `args` - The arguments from the CLI related to the query
+    /// - `config` - The config
+    fn resolve(args: &config::QueryArgs, config: &config::Config) -> anyhow::Result<Self> {
+        let role = match &args.role {
+            Some(name) => Some(config::role::Roles::load()?.get(name)?.clone()),
+            None => None,
+        };
+        let role_model = match &role {
+            Some(role) => role.model()?,
+            None => None,
+        };
+
+        let model = match (&args.model, role_model) {
+            (Some(model), _) => model.clone(),
+            (None, Some(model)) => model,
+            (None, None) => config.model.clone(),
+        };
+
+        let mut params = config.sampling.clone();
+        if let Some(role) = &role {
+            params = params.with_overrides(&role.sampling);
+        }
+        let params = params.with_overrides(&args.sampling);
+        check_params(&params, model.provider())?;
+
+        // An empty system prompt from the command line sends none
+        let system_prompt = args
+            .system
+            .as_ref()
+            .or(role.as_ref().map(|role| &role.system_prompt))
+            .or(config.system_prompt.as_ref())
+            .map(|prompt| prompt.trim().to_string())
+            .filter(|prompt| !prompt.is_empty());
+
+        Ok(Self {
+            model,
+            params,
+            system_prompt,
+        })
+    }
+}
+
+/// Checks the sampling parameters against the provider and warns about the ones it ignores
+///
+/// ### Arguments
+/// - `params` - The sampling parameters
+/// - `provider` - The provider the parameters will be sent to
+fn check_params(params: &SamplingParams, provider: Provider) -> Result<(), PgptError> {
+    params.validate(provider)?;
+    let unsupported = params.unsupported(provider);
+    if !unsupported.is_empty() {
+        eprintln!(
+            "{}",
+            format!(
+                "{} models do not support {}, ignoring",
+                provider,
+                unsupported.join(", ")
+            )
+            .yellow()
+        );
+    }
+    Ok(())
+}
+
+/// Sends the request with a spinner until the response starts, and renders the response as it is
+/// streamed or once it is complete
+///
+/// ### Arguments
+/// - `provider` - The provider to send the request to
+/// - `request` - The request to send
+/// - `model` - The model the request is sent to
+/// - `skin` - The skin to render the response with
+/// - `stream` - Whether to stream the response
+/// - `header_printed` - Whether the `Response from` header has been printed, which is updated
+fn render_response(
+    provider: &dyn ChatProvider,
+    request: &ChatRequest,
+    model: &Model,
+    skin: &MadSkin,
+    stream: bool,
+    header_printed: &mut bool,
+) -> anyhow::Result<ChatResponse> {
+    let spinner = create_spinner()?;
+    spinner.enable_steady_tick(std::time::Duration::from_millis(200));
+    if !stream {
+        let response = provider.chat(request);
+        spinner.finish_and_clear();
+        let response = response?;
+        if !response.text.is_empty() {
+            if !*header_printed {
+                *header_printed = true;
+                println!();
+                println!(
+                    "{}",
+                    format!("Response from {}", response.model.magenta()).cyan()
+                );
+            }
+            println!("{}", skin.term_text(&response.text));
+        }
+        return Ok(response);
+    }
+
+    let mut markdown = MarkdownStream::new(skin);
+    let response = provider.chat_stream(request, &mut |token| {
+        spinner.finish_and_clear();
+        if !*header_printed {
+            *header_printed = true;
+            println!();
+            println!(
+                "{}",
+                format!("Response from {}", model.api_model().magenta()).cyan()
+            );
+        }
+        markdown.push(token);
+    });
+    spinner.finish_and_clear();
+    markdown.finish();
+    let response = response?;
+    if !response.text.is_empty() {
+        println!();
+    }
+    Ok(response)
+}
+
 fn create_skin() -> MadSkin {
     let mut skin = MadSkin::default();
     skin.bold.set_fg(Yellow);
     skin.italic.set_fgbg(Yellow, rgb(30, 30, 40));
 
-    return skin;
+    skin
 }
 
 /// Handles logic for query command
@@ -57,121 +342,288 @@ fn create_skin() -> MadSkin {
 /// - `args` - An Arc value for the arguments from the CLI related to the query.
 /// - `config` - An Arc value for the config
 pub fn run_query(args: Arc<config::QueryArgs>, config: Arc<config::Config>) -> anyhow::Result<()> {
-    // Cloning to be used in separate thread
-    let args_clone = Arc::clone(&args);
-    let config_clone = Arc::clone(&config);
-
     // Visuals
-    let spinner = create_spinner()?;
     let skin = create_skin();
 
-    let model = match &args.model {
-        Some(model) => model.clone(),
-        None => config.model.clone(),
+    let QuerySettings {
+        model,
+        params,
+        system_prompt,
+    } = QuerySettings::resolve(&args, &config)?;
+    let system_prompt = system_prompt.as_deref();
+
+    let provider = provider::create_provider(&model, &config, args.base_url.as_deref())?;
+    let mut request_builder = ChatRequest::builder();
+    request_builder.model(&model).params(&params);
+
+    let session = config.session(args.session.as_deref())?;
+    let cache = config::utils::load_cache(&session.name)?;
+
+    let context = match &args.context {
+        Some(ctx) => *ctx,
+        None => session.context,
     };
-    let model_clone = Arc::new(model.clone());
-
-    let handle: JoinHandle<anyhow::Result<(GPTResponse, Vec<CacheValue>)>> =
-        std::thread::spawn(move || {
-            let gpt = GPTClient::new(&config_clone.api_key)?;
-            let mut query_builder = GPTQuery::builder();
-            query_builder.model(&model_clone);
-
-            let cache = config::utils::load_cache()?;
-
-            let context = match &args_clone.context {
-                Some(ctx) => *ctx,
-                None => config_clone.context,
-            };
-
-            // Adding cached messages up to context
-            let start = if context > cache.len() {
-                0
-            } else {
-                cache.len() - context
-            };
-            let context_messages = Vec::from(&cache[start..]);
-            for message in context_messages.iter() {
-                query_builder.message(GPTRole::User, &message.prompt);
-                query_builder.message(GPTRole::System, &message.response);
-            }
 
-            // Adding query
-            query_builder.message(GPTRole::User, &args_clone.query);
+    if let Some(system_prompt) = system_prompt {
+        request_builder.message(GPTRole::System, system_prompt);
+    }
+
+    // Adding cached messages up to context
+    let start = cache.len().saturating_sub(context);
+    let context_messages = Vec::from(&cache[start..]);
+    for message in context_messages.iter() {
+        request_builder.message(GPTRole::User, &message.prompt);
+        request_builder.message(GPTRole::Assistant, &message.response);
+    }
+
+    // Attached files are added to the prompt after the query
+    let mut prompt = args.query.clone();
+    if !args.files.is_empty() {
+        let files = attachment::format_files(&args.files)?;
+        if !files.is_empty() {
+            prompt = format!("{}\n\n{}", prompt, files);
+        }
+    }
+
+    // Adding query
+    if args.images.is_empty() {
+        request_builder.message(GPTRole::User, &prompt);
+    } else {
+        let images = args
+            .images
+            .iter()
+            .map(|path| GPTImageUrl::from_file(path))
+            .collect::<anyhow::Result<Vec<GPTImageUrl>>>()?;
+        request_builder.message_with_images(GPTRole::User, &prompt, &images);
+    }
+
+    let tools = ToolRegistry::builtin();
+    if args.tools {
+        request_builder.tools(&tools.definitions());
+    }
+
+    // JSON output is printed as is so it can be read by other programs
+    let response_format = match (&args.json_schema, args.json) {
+        (Some(path), _) => Some(ResponseFormat::from_schema_file(path)?),
+        (None, true) => Some(ResponseFormat::JsonObject),
+        (None, false) => None,
+    };
+    if let Some(response_format) = &response_format {
+        request_builder.response_format(response_format.clone());
+    }
+
+    if let Some(pick) = args.pick {
+        if pick == 0 || pick > args.choices {
+            return Err(PgptError::InvalidArgument(format!(
+                "--pick must be between 1 and the number of choices ({})",
+                args.choices
+            ))
+            .into());
+        }
+    }
+    request_builder.choices(args.choices);
+
+    if args.logprobs.is_some() {
+        if !provider.supports_logprobs() {
+            return Err(anyhow::anyhow!(
+                "{} models do not support log probabilities",
+                model.provider()
+            ));
+        }
+        request_builder.logprobs(args.top_logprobs.unwrap_or(0));
+    }
 
-            let query = query_builder.build()?;
+    let mut request = request_builder.build()?;
+    // Nothing is sent if the model cannot handle the request
+    let capabilities = model.capabilities();
+    check_capabilities(&model, &capabilities, &request)?;
 
-            // TODO remove this after testing complete
-            // println!("Sending query:\n{:?}", query);
-            let response = gpt.query(&query)?;
+    if args.show_context {
+        let mut shown: Vec<String> = Vec::new();
+        if let Some(system_prompt) = system_prompt {
+            shown.push(format!("{}:\n{}", "System".cyan(), system_prompt));
+        }
+        for message in context_messages.iter() {
+            if let Some(role) = &message.role {
+                shown.push(format!("{}: {}", "Role".yellow(), role));
+            }
+            shown.push(format!("{}:\n{}", "You said".yellow(), message.prompt));
+            shown.push(format!(
+                "{}:\n{}",
+                "GPT said".magenta(),
+                skin.term_text(&message.response)
+            ));
+        }
+        shown.push(format!("{}:\n{}", "You said".yellow(), prompt));
+        for image in args.images.iter() {
+            shown.push(format!("{}: {}", "Image".yellow(), image.display()));
+        }
+        // Keep stdout to just the JSON
+        if response_format.is_some() {
+            eprintln!("{}", shown.join("\n"));
+        } else {
+            println!("{}", shown.join("\n"));
+        }
+    }
 
-            let mut queue_cache: VecDeque<CacheValue> = VecDeque::from(context_messages.clone());
-            let cache_value = CacheValue {
-                prompt: args_clone.query.to_string(),
-                response: response.choices[0].message.content.to_string(),
-            };
-            queue_cache.push_back(cache_value);
+    // The response colored by its log probabilities is the only copy of it that is printed
+    let colored_logprobs = args.logprobs == Some(LogprobsDisplay::Color);
+    let mut usage = GPTUsage::default();
+    let mut header_printed = false;
+    let mut tool_rounds = 0;
+    // Tool calls are answered and sent back until the model gives its final message
+    let response = loop {
+        let response = if response_format.is_some() || args.choices > 1 || colored_logprobs {
+            let spinner = create_spinner()?;
+            spinner.enable_steady_tick(std::time::Duration::from_millis(200));
+            let response = chat_choices(provider.as_ref(), &request);
+            spinner.finish_and_clear();
+            response?
+        } else {
+            render_response(
+                provider.as_ref(),
+                &request,
+                &model,
+                &skin,
+                !args.no_stream && capabilities.streaming != Some(false),
+                &mut header_printed,
+            )?
+        };
+        usage += &response.usage;
 
-            if queue_cache.len() > config_clone.cache_length {
-                let diff = queue_cache.len() - config_clone.cache_length;
-                for _ in 0..diff {
-                    queue_cache.pop_front();
-                }
+        if response.tool_calls.is_empty() {
+            break response;
+        }
+        tool_rounds += 1;
+        if tool_rounds > MAX_TOOL_ROUNDS {
+            return Err(anyhow::anyhow!(
+                "The model was still calling tools after {} rounds",
+                MAX_TOOL_ROUNDS
+            ));
+        }
+
+        let mut assistant_message = GPTMessage::new(GPTRole::Assistant, &response.text);
+        assistant_message.tool_calls = Some(response.tool_calls.clone());
+        request.messages.push(assistant_message);
+        for tool_call in response.tool_calls.iter() {
+            eprintln!(
+                "{} {}({})",
+                "Calling tool".yellow(),
+                tool_call.function.name.cyan(),
+                tool_call.function.arguments
+            );
+            let output = tools.call(tool_call);
+            request
+                .messages
+                .push(GPTMessage::tool_result(&tool_call.id, &output));
+        }
+    };
+
+    if let Some(response_format) = &response_format {
+        let value = match response_format.validate(&response.text) {
+            Ok(value) => value,
+            Err(e) => {
+                eprintln!("{}", response.text);
+                return Err(e.into());
             }
+        };
+        if args.logprobs.is_some() {
+            let output = serde_json::json!({ "response": value, "logprobs": response.logprobs });
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        } else {
+            println!("{}", response.text.trim());
+        }
+    } else if let Some(display) = args.logprobs {
+        render::print_logprobs(&response.logprobs, display);
+    }
 
-            let updated_cache = Vec::from(queue_cache);
-            let cache_size = updated_cache.len();
-            config::utils::save_cache(updated_cache)?;
+    // Every choice is shown and only the picked one is saved
+    let mut response_text = response.text.clone();
+    if args.choices > 1 {
+        let choices = response.choices();
+        for (i, choice) in choices.iter().enumerate() {
+            println!();
             println!(
                 "{}",
                 format!(
-                    "Cache capacity {}/{}",
-                    cache_size, config_clone.cache_length
+                    "Choice {}/{} from {}",
+                    i + 1,
+                    choices.len(),
+                    response.model.magenta()
                 )
-                .green()
+                .cyan()
             );
-            Ok((response, context_messages))
-        });
-
-    while !handle.is_finished() {
-        spinner.tick();
-        std::thread::sleep(std::time::Duration::from_millis(200));
-    }
-
-    match handle.join() {
-        Ok(result) => match result {
-            Ok((response, context_messages)) => {
-                if args.show_context {
-                    for message in context_messages {
-                        println!("{}:\n{}", "You said".yellow(), message.prompt);
-                        println!(
-                            "{}:\n{}",
-                            "GPT said".magenta(),
-                            skin.term_text(&message.response)
-                        )
-                    }
-                    println!("{}:\n{}", "You said".yellow(), args.query);
-                }
-                println!("");
-                println!(
-                    "{}",
-                    format!("Response from {}", response.model.magenta()).cyan()
-                );
-                println!(
-                    "{}",
-                    skin.term_text(response.choices[0].message.content.as_str())
-                );
-                if args.cost {
-                    println!(
-                        "{}: ${:.6}",
-                        "Cost".green(),
-                        response.usage.total_cost(&model)
-                    );
-                }
+            println!("{}", skin.term_text(choice));
+        }
+        println!();
+        let pick = match args.pick {
+            Some(pick) => pick as usize,
+            None => ask_choice(choices.len())?,
+        };
+        response_text = match choices.get(pick - 1) {
+            Some(choice) => choice.to_string(),
+            None => {
+                return Err(PgptError::InvalidArgument(format!(
+                    "Cannot pick choice {}, only {} were returned",
+                    pick,
+                    choices.len()
+                ))
+                .into())
             }
-            Err(e) => return Err(anyhow::anyhow!(e)),
-        },
-        Err(_) => return Err(anyhow::anyhow!("Thread failed to execute!")),
+        };
+    }
+
+    let mut queue_cache: VecDeque<CacheValue> = VecDeque::from(context_messages);
+    let cache_value = CacheValue {
+        prompt,
+        response: response_text,
+        images: args
+            .images
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect(),
+        role: args.role.clone(),
+    };
+    queue_cache.push_back(cache_value);
+
+    if queue_cache.len() > session.cache_length {
+        let diff = queue_cache.len() - session.cache_length;
+        for _ in 0..diff {
+            queue_cache.pop_front();
+        }
+    }
+
+    let updated_cache = Vec::from(queue_cache);
+    let cache_size = updated_cache.len();
+    config::utils::save_cache(&session.name, updated_cache)?;
+    let cost = match usage.total_cost(&model, &config.pricing) {
+        Some(cost) => format!("{}: ${:.6}", "Cost".green(), cost),
+        None => format!(
+            "{}: unknown, add a price for `{}` to {:?}",
+            "Cost".green(),
+            model.api_model(),
+            config::utils::pricing_file_path()
+        ),
+    };
+    if response_format.is_some() {
+        // Keep stdout to just the JSON
+        if args.cost {
+            eprintln!("{}", cost);
+        }
+        return Ok(());
+    }
+
+    println!("{}", "Saved cache successfully!".green());
+    println!(
+        "{}",
+        format!(
+            "Cache capacity {}/{} in session {}",
+            cache_size, session.cache_length, session.name
+        )
+        .green()
+    );
+    if args.cost {
+        println!("{}", cost);
     }
 
     Ok(())
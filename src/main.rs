@@ -1,13 +1,36 @@
 use anyhow::Context;
 use pgpt::config;
+use pgpt::error::PgptError;
 
-fn main() -> anyhow::Result<()> {
-    match config::Config::parse_args() {
+fn run() -> anyhow::Result<()> {
+    match config::Config::parse_args()? {
         config::ParsedArgs::Query { args } => {
-            let config =
-                config::Config::load_config().with_context(|| format!("Failed to load config."))?;
+            let config = config::Config::load_config().context("Failed to load config.")?;
             pgpt::run_query(args, config)
         }
+        config::ParsedArgs::Chat { args } => {
+            let config = config::Config::load_config().context("Failed to load config.")?;
+            pgpt::chat::run_chat(args, config)
+        }
         config::ParsedArgs::Config { config } => config::Config::handle_config(&config),
+        config::ParsedArgs::Template { template } => template.run(),
+        config::ParsedArgs::Models { models } => {
+            let config = config::Config::load_config().context("Failed to load config.")?;
+            models.run(&config)
+        }
+        config::ParsedArgs::Pricing { pricing } => pricing.run(),
+        config::ParsedArgs::Session { session } => session.run(),
+        config::ParsedArgs::Role { role } => role.run(),
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {:?}", e);
+        // Typed errors get their own exit code so scripts can react to them
+        let code = e
+            .downcast_ref::<PgptError>()
+            .map_or(1, PgptError::exit_code);
+        std::process::exit(code);
     }
 }
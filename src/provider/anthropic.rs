@@ -0,0 +1,572 @@
+use crate::error::{parse_json, PgptError};
+use crate::gpt::{
+    GPTContent, GPTContentPart, GPTMessage, GPTPromptTokensDetails, GPTRole, GPTUsage,
+};
+use crate::provider::retry::{self, RetryPolicy};
+use crate::provider::{sse_data, ChatProvider, ChatRequest, ChatResponse};
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader};
+
+/// The base URL of the Anthropic API
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+
+/// The version of the Messages API the client is written against
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// The `max_tokens` sent when none is set, since the Messages API requires one
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+#[derive(Serialize, Debug)]
+pub struct AnthropicQuery {
+    pub model: String,
+    pub max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    pub messages: Vec<AnthropicMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AnthropicMessage {
+    pub role: GPTRole,
+    pub content: Vec<AnthropicBlock>,
+}
+
+/// A piece of the content of a request message
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicBlock {
+    Text { text: String },
+    Image { source: AnthropicImageSource },
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicImageSource {
+    Base64 { media_type: String, data: String },
+    Url { url: String },
+}
+
+impl AnthropicBlock {
+    /// Converts message content into content blocks
+    fn from_content(content: &GPTContent) -> Vec<Self> {
+        match content {
+            GPTContent::Text(text) => vec![Self::Text { text: text.clone() }],
+            GPTContent::Parts(parts) => parts
+                .iter()
+                .map(|part| match part {
+                    GPTContentPart::Text { text } => Self::Text { text: text.clone() },
+                    GPTContentPart::ImageUrl { image_url } => Self::Image {
+                        source: match image_url.base64_data() {
+                            Some((media_type, data)) => AnthropicImageSource::Base64 {
+                                media_type: media_type.to_string(),
+                                data: data.to_string(),
+                            },
+                            None => AnthropicImageSource::Url {
+                                url: image_url.url.clone(),
+                            },
+                        },
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+pub struct AnthropicResponse {
+    pub id: String,
+    pub model: String,
+    pub content: Vec<AnthropicContent>,
+    pub stop_reason: Option<String>,
+    pub usage: AnthropicUsage,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicContent {
+    Text {
+        text: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct AnthropicUsage {
+    #[serde(default)]
+    pub input_tokens: i32,
+    #[serde(default)]
+    pub output_tokens: i32,
+    #[serde(default)]
+    pub cache_creation_input_tokens: i32,
+    #[serde(default)]
+    pub cache_read_input_tokens: i32,
+}
+
+impl From<AnthropicUsage> for GPTUsage {
+    fn from(usage: AnthropicUsage) -> Self {
+        // `input_tokens` does not include the tokens written to or read from the prompt cache
+        let prompt_tokens =
+            usage.input_tokens + usage.cache_creation_input_tokens + usage.cache_read_input_tokens;
+        Self {
+            prompt_tokens,
+            completion_tokens: usage.output_tokens,
+            total_tokens: prompt_tokens + usage.output_tokens,
+            prompt_tokens_details: GPTPromptTokensDetails {
+                cached_tokens: usage.cache_read_input_tokens,
+                cache_write_tokens: usage.cache_creation_input_tokens,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// A server-sent event from a streamed response
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicStreamEvent {
+    MessageStart {
+        message: AnthropicStreamMessage,
+    },
+    ContentBlockDelta {
+        delta: AnthropicDelta,
+    },
+    MessageDelta {
+        delta: AnthropicMessageDelta,
+        usage: AnthropicUsage,
+    },
+    /// The error details are classified from the raw event by [`PgptError::from_error_body`]
+    Error,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AnthropicStreamMessage {
+    pub model: String,
+    pub usage: AnthropicUsage,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicDelta {
+    TextDelta {
+        text: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AnthropicMessageDelta {
+    pub stop_reason: Option<String>,
+}
+
+impl AnthropicQuery {
+    /// Creates the Anthropic query for a provider-neutral request. System messages are moved to the
+    /// separate `system` field, images become image blocks and consecutive messages from the same
+    /// role are merged.
+    ///
+    /// ### Arguments
+    /// - `request` - The request to convert
+    pub fn from_request(request: &ChatRequest) -> Self {
+        let mut system: Vec<String> = Vec::new();
+        let mut messages: Vec<AnthropicMessage> = Vec::new();
+        for GPTMessage { role, content, .. } in request.messages.iter() {
+            let role = match role {
+                GPTRole::System => {
+                    system.push(content.text());
+                    continue;
+                }
+                // Tools are never sent to Anthropic, so tool results can only be plain user text
+                GPTRole::User | GPTRole::Tool => GPTRole::User,
+                GPTRole::Assistant => GPTRole::Assistant,
+            };
+            let blocks = AnthropicBlock::from_content(content);
+            match messages.last_mut() {
+                Some(last)
+                    if std::mem::discriminant(&last.role) == std::mem::discriminant(&role) =>
+                {
+                    last.content.extend(blocks);
+                }
+                _ => messages.push(AnthropicMessage {
+                    role,
+                    content: blocks,
+                }),
+            }
+        }
+
+        Self {
+            model: request.model.api_model(),
+            max_tokens: request.params.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            system: if system.is_empty() {
+                None
+            } else {
+                Some(system.join("\n\n"))
+            },
+            messages,
+            temperature: request.params.temperature,
+            top_p: request.params.top_p,
+            stop_sequences: request.params.stop.clone(),
+            stream: false,
+        }
+    }
+}
+
+/// The models offered by the Anthropic API
+#[derive(Deserialize, Debug)]
+pub struct AnthropicModelList {
+    pub data: Vec<AnthropicModelInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AnthropicModelInfo {
+    pub id: String,
+}
+
+pub struct AnthropicClient {
+    http_client: Client,
+    messages_url: String,
+    models_url: String,
+    retry_policy: RetryPolicy,
+}
+
+impl AnthropicClient {
+    fn create_http_client(api_key: &str) -> anyhow::Result<Client> {
+        let user_agent = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert(USER_AGENT, HeaderValue::from_str(&user_agent)?);
+        headers.insert("x-api-key", HeaderValue::from_str(api_key)?);
+        headers.insert(
+            "anthropic-version",
+            HeaderValue::from_static(ANTHROPIC_VERSION),
+        );
+        let client = Client::builder().default_headers(headers).build()?;
+        Ok(client)
+    }
+
+    /// Creates a client for the Anthropic Messages API
+    ///
+    /// ### Arguments
+    /// - `api_key` - The API key used to authorize requests
+    /// - `base_url` - The base URL of the API (e.g. `https://api.anthropic.com/v1`)
+    pub fn new(api_key: &str, base_url: &str) -> anyhow::Result<Self> {
+        let http_client = Self::create_http_client(api_key)?;
+        let messages_url = format!("{}/messages", base_url.trim_end_matches('/'));
+        let models_url = format!("{}/models?limit=1000", base_url.trim_end_matches('/'));
+        Ok(Self {
+            http_client,
+            messages_url,
+            models_url,
+            retry_policy: RetryPolicy::default(),
+        })
+    }
+
+    /// Sets how failed requests are retried
+    ///
+    /// ### Arguments
+    /// - `retry_policy` - The retry policy to use
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sends the query, converting error responses into errors
+    fn send(&self, query: &AnthropicQuery) -> anyhow::Result<Response> {
+        let response: Response = retry::send(
+            self.http_client.post(&self.messages_url).json(query),
+            &self.retry_policy,
+        )?;
+
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            Err(PgptError::from_response(response).into())
+        }
+    }
+}
+
+impl ChatProvider for AnthropicClient {
+    fn list_models(&self) -> anyhow::Result<Vec<String>> {
+        let response: Response =
+            retry::send(self.http_client.get(&self.models_url), &self.retry_policy)?;
+        if !response.status().is_success() {
+            return Err(PgptError::from_response(response).into());
+        }
+        let list: AnthropicModelList = parse_json(response)?;
+        Ok(list.data.into_iter().map(|model| model.id).collect())
+    }
+
+    fn chat(&self, request: &ChatRequest) -> anyhow::Result<ChatResponse> {
+        let query = AnthropicQuery::from_request(request);
+        let response: AnthropicResponse = parse_json(self.send(&query)?)?;
+        let text = response
+            .content
+            .iter()
+            .filter_map(|content| match content {
+                AnthropicContent::Text { text } => Some(text.as_str()),
+                AnthropicContent::Other => None,
+            })
+            .collect::<String>();
+        Ok(ChatResponse {
+            model: response.model,
+            text,
+            usage: response.usage.into(),
+            finish_reason: response.stop_reason,
+            tool_calls: Vec::new(),
+            alternatives: Vec::new(),
+            logprobs: Vec::new(),
+        })
+    }
+
+    fn chat_stream(
+        &self,
+        request: &ChatRequest,
+        on_token: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<ChatResponse> {
+        let mut query = AnthropicQuery::from_request(request);
+        query.stream = true;
+        let response = self.send(&query)?;
+        read_stream(BufReader::new(response), &query.model, on_token)
+    }
+}
+
+/// Reads a streamed response, calling `on_token` with each piece of text as it arrives
+///
+/// ### Arguments
+/// - `reader` - The server-sent events of the response
+/// - `model` - The model the request was sent to, until the stream reports it
+/// - `on_token` - Called with each piece of the response text in order
+fn read_stream(
+    reader: impl BufRead,
+    model: &str,
+    on_token: &mut dyn FnMut(&str),
+) -> anyhow::Result<ChatResponse> {
+    let mut chat_response = ChatResponse {
+        model: model.to_string(),
+        text: String::new(),
+        usage: GPTUsage::default(),
+        finish_reason: None,
+        tool_calls: Vec::new(),
+        alternatives: Vec::new(),
+        logprobs: Vec::new(),
+    };
+    for line in reader.lines() {
+        let line = line.map_err(PgptError::from)?;
+        let data = match sse_data(&line) {
+            Some(data) => data,
+            None => continue,
+        };
+
+        let event: AnthropicStreamEvent = serde_json::from_str(data)
+            .map_err(|e| PgptError::MalformedResponse(format!("{}: {}", e, data)))?;
+        match event {
+            AnthropicStreamEvent::MessageStart { message } => {
+                chat_response.model = message.model;
+                chat_response.usage = message.usage.into();
+            }
+            AnthropicStreamEvent::ContentBlockDelta {
+                delta: AnthropicDelta::TextDelta { text },
+            } => {
+                chat_response.text.push_str(&text);
+                on_token(&text);
+            }
+            AnthropicStreamEvent::MessageDelta { delta, usage } => {
+                chat_response.finish_reason = delta.stop_reason;
+                chat_response.usage.completion_tokens = usage.output_tokens;
+            }
+            AnthropicStreamEvent::Error => {
+                return Err(PgptError::from_error_body(data).into());
+            }
+            _ => {}
+        }
+    }
+
+    chat_response.usage.total_tokens =
+        chat_response.usage.prompt_tokens + chat_response.usage.completion_tokens;
+    Ok(chat_response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::model::Model;
+    use crate::gpt::GPTImageUrl;
+    use crate::provider::SamplingParams;
+    use serde_json::json;
+
+    /// Reads a stream made of the given events, returning the response and the tokens passed to
+    /// `on_token`
+    fn read_events(events: &[serde_json::Value]) -> (anyhow::Result<ChatResponse>, Vec<String>) {
+        let stream: String = events
+            .iter()
+            .map(|event| {
+                format!(
+                    "event: {}\ndata: {}\n\n",
+                    event["type"].as_str().unwrap(),
+                    event
+                )
+            })
+            .collect();
+        let mut tokens = Vec::new();
+        let response = read_stream(stream.as_bytes(), "claude-test", &mut |token| {
+            tokens.push(token.to_string())
+        });
+        (response, tokens)
+    }
+
+    #[test]
+    fn query_moves_system_messages_and_merges_roles() {
+        let image = GPTImageUrl {
+            url: String::from("data:image/png;base64,aGVsbG8="),
+            detail: None,
+        };
+        let request = ChatRequest::builder()
+            .model(&Model::Claude35Haiku)
+            .params(&SamplingParams {
+                temperature: Some(0.5),
+                ..Default::default()
+            })
+            .message(GPTRole::System, "Be brief.")
+            .message(GPTRole::User, "Hi")
+            .message_with_images(GPTRole::User, "What is this?", &[image])
+            .message(GPTRole::Assistant, "A picture.")
+            .build()
+            .unwrap();
+        let query = serde_json::to_value(AnthropicQuery::from_request(&request)).unwrap();
+
+        assert_eq!(
+            query,
+            json!({
+                "model": "claude-3-5-haiku-latest",
+                "max_tokens": DEFAULT_MAX_TOKENS,
+                "system": "Be brief.",
+                "messages": [
+                    {
+                        "role": "user",
+                        "content": [
+                            {"type": "text", "text": "Hi"},
+                            {"type": "text", "text": "What is this?"},
+                            {
+                                "type": "image",
+                                "source": {
+                                    "type": "base64",
+                                    "media_type": "image/png",
+                                    "data": "aGVsbG8="
+                                }
+                            }
+                        ]
+                    },
+                    {
+                        "role": "assistant",
+                        "content": [{"type": "text", "text": "A picture."}]
+                    }
+                ],
+                "temperature": 0.5
+            })
+        );
+    }
+
+    #[test]
+    fn response_text_and_usage_are_read() {
+        let response: AnthropicResponse = serde_json::from_value(json!({
+            "id": "msg_1",
+            "type": "message",
+            "model": "claude-test",
+            "content": [
+                {"type": "text", "text": "Hello"},
+                {"type": "tool_use", "id": "tool_1", "name": "f", "input": {}}
+            ],
+            "stop_reason": "end_turn",
+            "usage": {
+                "input_tokens": 10,
+                "output_tokens": 5,
+                "cache_creation_input_tokens": 4,
+                "cache_read_input_tokens": 3
+            }
+        }))
+        .unwrap();
+
+        assert!(matches!(
+            response.content.as_slice(),
+            [AnthropicContent::Text { text }, AnthropicContent::Other] if text == "Hello"
+        ));
+        let usage = GPTUsage::from(response.usage);
+        assert_eq!(usage.prompt_tokens, 17);
+        assert_eq!(usage.completion_tokens, 5);
+        assert_eq!(usage.prompt_tokens_details.cached_tokens, 3);
+        assert_eq!(usage.prompt_tokens_details.cache_write_tokens, 4);
+    }
+
+    #[test]
+    fn stream_text_and_usage_are_read() {
+        let (response, tokens) = read_events(&[
+            json!({
+                "type": "message_start",
+                "message": {"model": "claude-3-5-haiku-20241022", "usage": {"input_tokens": 12, "output_tokens": 1}}
+            }),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "ping"}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hel"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "lo"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 7}}),
+            json!({"type": "message_stop"}),
+        ]);
+        let response = response.unwrap();
+
+        assert_eq!(tokens, ["Hel", "lo"]);
+        assert_eq!(response.text, "Hello");
+        assert_eq!(response.model, "claude-3-5-haiku-20241022");
+        assert_eq!(response.finish_reason.as_deref(), Some("end_turn"));
+        assert_eq!(response.usage.prompt_tokens, 12);
+        assert_eq!(response.usage.completion_tokens, 7);
+        assert_eq!(response.usage.total_tokens, 19);
+    }
+
+    #[test]
+    fn stream_error_event_is_classified() {
+        let (response, tokens) = read_events(&[
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hi"}}),
+            json!({"type": "error", "error": {"type": "rate_limit_error", "message": "Slow down"}}),
+        ]);
+        let error = response.unwrap_err().downcast::<PgptError>().unwrap();
+
+        assert_eq!(tokens, ["Hi"]);
+        assert!(matches!(&error, PgptError::RateLimit(message) if message == "Slow down"));
+
+        let (response, _) = read_events(&[json!({
+            "type": "error",
+            "error": {"type": "overloaded_error", "message": "Overloaded"}
+        })]);
+        let error = response.unwrap_err().downcast::<PgptError>().unwrap();
+        assert!(matches!(error, PgptError::Api { status: None, .. }));
+        assert_eq!(error.to_string(), "API error: Overloaded");
+    }
+
+    #[test]
+    fn malformed_stream_event_fails() {
+        let mut tokens = Vec::new();
+        let response = read_stream(
+            "data: {not json}\n".as_bytes(),
+            "claude-test",
+            &mut |token| tokens.push(token.to_string()),
+        );
+        let error = response.unwrap_err().downcast::<PgptError>().unwrap();
+
+        assert!(matches!(error, PgptError::MalformedResponse(_)));
+        assert!(tokens.is_empty());
+    }
+}
@@ -0,0 +1,762 @@
+use crate::error::{parse_json, PgptError};
+use crate::gpt::{
+    GPTCompletionTokensDetails, GPTContent, GPTContentPart, GPTMessage, GPTPromptTokensDetails,
+    GPTRole, GPTUsage,
+};
+use crate::provider::retry::{self, RetryPolicy};
+use crate::provider::{sse_data, ChatProvider, ChatRequest, ChatResponse, ResponseFormat};
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader};
+
+/// The base URL of the Gemini API
+pub const DEFAULT_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+/// Finish reasons that mean the response was stopped by a content filter
+const BLOCKED_FINISH_REASONS: [&str; 5] = [
+    "SAFETY",
+    "RECITATION",
+    "BLOCKLIST",
+    "PROHIBITED_CONTENT",
+    "SPII",
+];
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiQuery {
+    pub contents: Vec<GeminiContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<GeminiContent>,
+    #[serde(skip_serializing_if = "GeminiGenerationConfig::is_empty")]
+    pub generation_config: GeminiGenerationConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct GeminiContent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub parts: Vec<GeminiPart>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiPart {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inline_data: Option<GeminiBlob>,
+}
+
+/// Inline file data, such as an image
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiBlob {
+    pub mime_type: String,
+    pub data: String,
+}
+
+impl GeminiPart {
+    fn text(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            inline_data: None,
+        }
+    }
+
+    /// Converts message content into parts. Only base64 images can be sent inline, so images
+    /// referenced by a web URL are skipped.
+    fn from_content(content: &GPTContent) -> Vec<Self> {
+        match content {
+            GPTContent::Text(text) => vec![Self::text(text)],
+            GPTContent::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    GPTContentPart::Text { text } => Some(Self::text(text)),
+                    GPTContentPart::ImageUrl { image_url } => {
+                        image_url.base64_data().map(|(mime_type, data)| Self {
+                            text: String::new(),
+                            inline_data: Some(GeminiBlob {
+                                mime_type: mime_type.to_string(),
+                                data: data.to_string(),
+                            }),
+                        })
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiGenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidate_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_json_schema: Option<serde_json::Value>,
+}
+
+impl GeminiGenerationConfig {
+    fn is_empty(&self) -> bool {
+        self.temperature.is_none()
+            && self.top_p.is_none()
+            && self.max_output_tokens.is_none()
+            && self.seed.is_none()
+            && self.stop_sequences.is_none()
+            && self.presence_penalty.is_none()
+            && self.frequency_penalty.is_none()
+            && self.candidate_count.is_none()
+            && self.response_mime_type.is_none()
+    }
+}
+
+/// A full response, or a single event of a streamed response
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiResponse {
+    #[serde(default)]
+    pub candidates: Vec<GeminiCandidate>,
+    pub prompt_feedback: Option<GeminiPromptFeedback>,
+    pub usage_metadata: Option<GeminiUsage>,
+    pub model_version: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiCandidate {
+    #[serde(default)]
+    pub content: GeminiContent,
+    pub finish_reason: Option<String>,
+    #[serde(default)]
+    pub safety_ratings: Vec<GeminiSafetyRating>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiPromptFeedback {
+    pub block_reason: Option<String>,
+    #[serde(default)]
+    pub safety_ratings: Vec<GeminiSafetyRating>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GeminiSafetyRating {
+    pub category: String,
+    pub probability: String,
+    #[serde(default)]
+    pub blocked: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiUsage {
+    #[serde(default)]
+    pub prompt_token_count: i32,
+    #[serde(default)]
+    pub candidates_token_count: i32,
+    #[serde(default)]
+    pub total_token_count: i32,
+    #[serde(default)]
+    pub cached_content_token_count: i32,
+    #[serde(default)]
+    pub thoughts_token_count: i32,
+}
+
+impl From<GeminiUsage> for GPTUsage {
+    fn from(usage: GeminiUsage) -> Self {
+        // Thinking tokens are billed as output but are not part of `candidatesTokenCount`
+        Self {
+            prompt_tokens: usage.prompt_token_count,
+            completion_tokens: usage.candidates_token_count + usage.thoughts_token_count,
+            total_tokens: usage.total_token_count,
+            prompt_tokens_details: GPTPromptTokensDetails {
+                cached_tokens: usage.cached_content_token_count,
+                ..Default::default()
+            },
+            completion_tokens_details: GPTCompletionTokensDetails {
+                reasoning_tokens: usage.thoughts_token_count,
+            },
+        }
+    }
+}
+
+impl GeminiQuery {
+    /// Creates the Gemini query for a provider-neutral request. System messages are moved to
+    /// `systemInstruction`, assistant messages use the `model` role and consecutive messages from
+    /// the same role are merged.
+    ///
+    /// ### Arguments
+    /// - `request` - The request to convert
+    pub fn from_request(request: &ChatRequest) -> Self {
+        let mut system: Vec<GeminiPart> = Vec::new();
+        let mut contents: Vec<GeminiContent> = Vec::new();
+        for GPTMessage { role, content, .. } in request.messages.iter() {
+            let role = match role {
+                GPTRole::System => {
+                    system.extend(GeminiPart::from_content(content));
+                    continue;
+                }
+                // Tools are never sent to Gemini, so tool results can only be plain user text
+                GPTRole::User | GPTRole::Tool => "user",
+                GPTRole::Assistant => "model",
+            };
+            let parts = GeminiPart::from_content(content);
+            match contents.last_mut() {
+                Some(last) if last.role.as_deref() == Some(role) => last.parts.extend(parts),
+                _ => contents.push(GeminiContent {
+                    role: Some(role.to_string()),
+                    parts,
+                }),
+            }
+        }
+
+        Self {
+            contents,
+            system_instruction: if system.is_empty() {
+                None
+            } else {
+                Some(GeminiContent {
+                    role: None,
+                    parts: system,
+                })
+            },
+            generation_config: GeminiGenerationConfig {
+                temperature: request.params.temperature,
+                top_p: request.params.top_p,
+                max_output_tokens: request.params.max_tokens,
+                seed: request.params.seed,
+                stop_sequences: request.params.stop.clone(),
+                presence_penalty: request.params.presence_penalty,
+                frequency_penalty: request.params.frequency_penalty,
+                candidate_count: (request.choices > 1).then_some(request.choices),
+                response_mime_type: request
+                    .response_format
+                    .as_ref()
+                    .map(|_| String::from("application/json")),
+                response_json_schema: match &request.response_format {
+                    Some(ResponseFormat::JsonSchema { schema, .. }) => Some(schema.clone()),
+                    _ => None,
+                },
+            },
+        }
+    }
+}
+
+impl GeminiCandidate {
+    /// Returns the text of the candidate's parts
+    fn text(&self) -> String {
+        self.content
+            .parts
+            .iter()
+            .map(|part| part.text.as_str())
+            .collect()
+    }
+}
+
+impl GeminiResponse {
+    /// Returns an error if the prompt or the response was blocked by Gemini's safety filters
+    fn check_blocked(&self) -> Result<(), PgptError> {
+        if let Some(feedback) = &self.prompt_feedback {
+            if let Some(reason) = &feedback.block_reason {
+                return Err(PgptError::ContentBlocked(format!(
+                    "Gemini blocked the prompt ({}){}",
+                    reason,
+                    flagged_categories(&feedback.safety_ratings)
+                )));
+            }
+        }
+        for candidate in self.candidates.iter() {
+            if let Some(reason) = &candidate.finish_reason {
+                if BLOCKED_FINISH_REASONS.contains(&reason.as_str()) {
+                    return Err(PgptError::ContentBlocked(format!(
+                        "Gemini stopped the response ({}){}",
+                        reason,
+                        flagged_categories(&candidate.safety_ratings)
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the text of the first candidate
+    fn text(&self) -> String {
+        self.candidates
+            .first()
+            .map(GeminiCandidate::text)
+            .unwrap_or_default()
+    }
+}
+
+/// Lists the safety categories that were flagged, for error messages
+fn flagged_categories(ratings: &[GeminiSafetyRating]) -> String {
+    let flagged: Vec<String> = ratings
+        .iter()
+        .filter(|rating| rating.blocked || matches!(rating.probability.as_str(), "MEDIUM" | "HIGH"))
+        .map(|rating| format!("{} ({})", rating.category, rating.probability))
+        .collect();
+    if flagged.is_empty() {
+        String::new()
+    } else {
+        format!(" - flagged: {}", flagged.join(", "))
+    }
+}
+
+/// The models offered by the Gemini API
+#[derive(Deserialize, Debug)]
+pub struct GeminiModelList {
+    #[serde(default)]
+    pub models: Vec<GeminiModelInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiModelInfo {
+    /// The resource name of the model (e.g. `models/gemini-2.0-flash`)
+    pub name: String,
+    #[serde(default)]
+    pub supported_generation_methods: Vec<String>,
+}
+
+pub struct GeminiClient {
+    http_client: Client,
+    base_url: String,
+    retry_policy: RetryPolicy,
+}
+
+impl GeminiClient {
+    fn create_http_client(api_key: &str) -> anyhow::Result<Client> {
+        let user_agent = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert(USER_AGENT, HeaderValue::from_str(&user_agent)?);
+        headers.insert("x-goog-api-key", HeaderValue::from_str(api_key)?);
+        let client = Client::builder().default_headers(headers).build()?;
+        Ok(client)
+    }
+
+    /// Creates a client for the Gemini API
+    ///
+    /// ### Arguments
+    /// - `api_key` - The API key used to authorize requests
+    /// - `base_url` - The base URL of the API (e.g. `https://generativelanguage.googleapis.com/v1beta`)
+    pub fn new(api_key: &str, base_url: &str) -> anyhow::Result<Self> {
+        let http_client = Self::create_http_client(api_key)?;
+        Ok(Self {
+            http_client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            retry_policy: RetryPolicy::default(),
+        })
+    }
+
+    /// Sets how failed requests are retried
+    ///
+    /// ### Arguments
+    /// - `retry_policy` - The retry policy to use
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sends the query, converting error responses into errors
+    ///
+    /// ### Arguments
+    /// - `model` - The API name of the model
+    /// - `method` - The API method (e.g. `generateContent`)
+    /// - `query` - The query to send
+    fn send(&self, model: &str, method: &str, query: &GeminiQuery) -> anyhow::Result<Response> {
+        let url = format!("{}/models/{}:{}", self.base_url, model, method);
+        let response: Response =
+            retry::send(self.http_client.post(url).json(query), &self.retry_policy)?;
+
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            Err(PgptError::from_response(response).into())
+        }
+    }
+}
+
+impl ChatProvider for GeminiClient {
+    fn list_models(&self) -> anyhow::Result<Vec<String>> {
+        let url = format!("{}/models?pageSize=1000", self.base_url);
+        let response: Response = retry::send(self.http_client.get(url), &self.retry_policy)?;
+        if !response.status().is_success() {
+            return Err(PgptError::from_response(response).into());
+        }
+        // Embedding and other models that cannot chat are left out
+        let list: GeminiModelList = parse_json(response)?;
+        Ok(list
+            .models
+            .into_iter()
+            .filter(|model| {
+                model
+                    .supported_generation_methods
+                    .iter()
+                    .any(|method| method == "generateContent")
+            })
+            .map(|model| {
+                model
+                    .name
+                    .strip_prefix("models/")
+                    .map(String::from)
+                    .unwrap_or(model.name)
+            })
+            .collect())
+    }
+
+    fn supports_choices(&self) -> bool {
+        true
+    }
+
+    fn chat(&self, request: &ChatRequest) -> anyhow::Result<ChatResponse> {
+        let model = request.model.api_model();
+        let query = GeminiQuery::from_request(request);
+        let response: GeminiResponse = parse_json(self.send(&model, "generateContent", &query)?)?;
+        response.check_blocked()?;
+        Ok(ChatResponse {
+            text: response.text(),
+            finish_reason: response
+                .candidates
+                .first()
+                .and_then(|candidate| candidate.finish_reason.clone()),
+            model: response.model_version.unwrap_or(model),
+            usage: response.usage_metadata.unwrap_or_default().into(),
+            tool_calls: Vec::new(),
+            alternatives: response
+                .candidates
+                .iter()
+                .skip(1)
+                .map(GeminiCandidate::text)
+                .collect(),
+            logprobs: Vec::new(),
+        })
+    }
+
+    fn chat_stream(
+        &self,
+        request: &ChatRequest,
+        on_token: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<ChatResponse> {
+        let model = request.model.api_model();
+        let query = GeminiQuery::from_request(request);
+        let response = self.send(&model, "streamGenerateContent?alt=sse", &query)?;
+        read_stream(BufReader::new(response), &model, on_token)
+    }
+}
+
+/// Reads a streamed response, calling `on_token` with each piece of text as it arrives
+///
+/// ### Arguments
+/// - `reader` - The server-sent events of the response
+/// - `model` - The model the request was sent to, until the stream reports its version
+/// - `on_token` - Called with each piece of the response text in order
+fn read_stream(
+    reader: impl BufRead,
+    model: &str,
+    on_token: &mut dyn FnMut(&str),
+) -> anyhow::Result<ChatResponse> {
+    let mut chat_response = ChatResponse {
+        model: model.to_string(),
+        text: String::new(),
+        usage: GPTUsage::default(),
+        finish_reason: None,
+        tool_calls: Vec::new(),
+        alternatives: Vec::new(),
+        logprobs: Vec::new(),
+    };
+    for line in reader.lines() {
+        let line = line.map_err(PgptError::from)?;
+        let data = match sse_data(&line) {
+            Some(data) => data,
+            None => continue,
+        };
+
+        let chunk: GeminiResponse = serde_json::from_str(data)
+            .map_err(|e| PgptError::MalformedResponse(format!("{}: {}", e, data)))?;
+        chunk.check_blocked()?;
+        let text = chunk.text();
+        if !text.is_empty() {
+            chat_response.text.push_str(&text);
+            on_token(&text);
+        }
+        if let Some(candidate) = chunk.candidates.first() {
+            if candidate.finish_reason.is_some() {
+                chat_response.finish_reason = candidate.finish_reason.clone();
+            }
+        }
+        if let Some(version) = chunk.model_version {
+            chat_response.model = version;
+        }
+        if let Some(usage) = chunk.usage_metadata {
+            chat_response.usage = usage.into();
+        }
+    }
+    Ok(chat_response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::model::Model;
+    use crate::gpt::{GPTFunctionCall, GPTImageUrl, GPTToolCall};
+    use crate::provider::SamplingParams;
+    use serde_json::json;
+
+    /// Reads a stream made of the given events, returning the response and the tokens passed to
+    /// `on_token`
+    fn read_events(events: &[serde_json::Value]) -> (anyhow::Result<ChatResponse>, Vec<String>) {
+        let stream: String = events
+            .iter()
+            .map(|event| format!("data: {}\n\n", event))
+            .collect();
+        let mut tokens = Vec::new();
+        let response = read_stream(stream.as_bytes(), "gemini-test", &mut |token| {
+            tokens.push(token.to_string())
+        });
+        (response, tokens)
+    }
+
+    fn response(value: serde_json::Value) -> GeminiResponse {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn query_moves_system_instruction_and_maps_roles() {
+        let request = ChatRequest::builder()
+            .model(&Model::Gemini20Flash)
+            .params(&SamplingParams {
+                max_tokens: Some(100),
+                ..Default::default()
+            })
+            .message(GPTRole::System, "Be brief.")
+            .message(GPTRole::User, "Hi")
+            .message(GPTRole::User, "Anyone there?")
+            .message(GPTRole::Assistant, "Hello!")
+            .message(GPTRole::System, "Answer in French.")
+            .message(GPTRole::User, "Bye")
+            .build()
+            .unwrap();
+        let query = serde_json::to_value(GeminiQuery::from_request(&request)).unwrap();
+
+        assert_eq!(
+            query,
+            json!({
+                "contents": [
+                    {"role": "user", "parts": [{"text": "Hi"}, {"text": "Anyone there?"}]},
+                    {"role": "model", "parts": [{"text": "Hello!"}]},
+                    {"role": "user", "parts": [{"text": "Bye"}]}
+                ],
+                "systemInstruction": {
+                    "parts": [{"text": "Be brief."}, {"text": "Answer in French."}]
+                },
+                "generationConfig": {"maxOutputTokens": 100}
+            })
+        );
+    }
+
+    #[test]
+    fn base64_images_are_sent_inline() {
+        let images = [
+            GPTImageUrl {
+                url: String::from("data:image/jpeg;base64,aGVsbG8="),
+                detail: None,
+            },
+            // Web URLs cannot be sent inline
+            GPTImageUrl {
+                url: String::from("https://example.com/cat.png"),
+                detail: None,
+            },
+        ];
+        let request = ChatRequest::builder()
+            .model(&Model::Gemini20Flash)
+            .message_with_images(GPTRole::User, "What is this?", &images)
+            .build()
+            .unwrap();
+        let query = serde_json::to_value(GeminiQuery::from_request(&request)).unwrap();
+
+        assert_eq!(
+            query["contents"],
+            json!([{
+                "role": "user",
+                "parts": [
+                    {"text": "What is this?"},
+                    {"inlineData": {"mimeType": "image/jpeg", "data": "aGVsbG8="}}
+                ]
+            }])
+        );
+    }
+
+    #[test]
+    fn tool_calls_and_results_are_sent_as_text() {
+        let mut request = ChatRequest::builder()
+            .model(&Model::Gemini20Flash)
+            .message(GPTRole::User, "What time is it?")
+            .build()
+            .unwrap();
+        let mut call = GPTMessage::new(GPTRole::Assistant, "Let me check.");
+        call.tool_calls = Some(vec![GPTToolCall {
+            id: String::from("call_1"),
+            tool_type: String::from("function"),
+            function: GPTFunctionCall {
+                name: String::from("time"),
+                arguments: String::from("{}"),
+            },
+        }]);
+        request.messages.push(call);
+        request
+            .messages
+            .push(GPTMessage::tool_result("call_1", "12:00"));
+        let query = serde_json::to_value(GeminiQuery::from_request(&request)).unwrap();
+
+        assert_eq!(
+            query["contents"],
+            json!([
+                {"role": "user", "parts": [{"text": "What time is it?"}]},
+                {"role": "model", "parts": [{"text": "Let me check."}]},
+                {"role": "user", "parts": [{"text": "12:00"}]}
+            ])
+        );
+    }
+
+    #[test]
+    fn json_schema_and_choices_are_set_in_the_generation_config() {
+        let mut request = ChatRequest::builder()
+            .model(&Model::Gemini20Flash)
+            .message(GPTRole::User, "Hi")
+            .build()
+            .unwrap();
+        request.choices = 2;
+        request.response_format = Some(ResponseFormat::JsonSchema {
+            name: String::from("answer"),
+            schema: json!({"type": "object"}),
+        });
+        let query = serde_json::to_value(GeminiQuery::from_request(&request)).unwrap();
+
+        assert_eq!(
+            query["generationConfig"],
+            json!({
+                "candidateCount": 2,
+                "responseMimeType": "application/json",
+                "responseJsonSchema": {"type": "object"}
+            })
+        );
+    }
+
+    #[test]
+    fn response_text_candidates_and_usage_are_read() {
+        let response = response(json!({
+            "candidates": [
+                {"content": {"role": "model", "parts": [{"text": "Hel"}, {"text": "lo"}]}, "finishReason": "STOP"},
+                {"content": {"role": "model", "parts": [{"text": "Hi"}]}, "finishReason": "STOP"}
+            ],
+            "usageMetadata": {
+                "promptTokenCount": 10,
+                "candidatesTokenCount": 4,
+                "thoughtsTokenCount": 6,
+                "cachedContentTokenCount": 2,
+                "totalTokenCount": 20
+            },
+            "modelVersion": "gemini-2.0-flash-001"
+        }));
+
+        assert!(response.check_blocked().is_ok());
+        assert_eq!(response.text(), "Hello");
+        assert_eq!(response.candidates[1].text(), "Hi");
+        let usage = GPTUsage::from(response.usage_metadata.unwrap());
+        assert_eq!(usage.prompt_tokens, 10);
+        assert_eq!(usage.completion_tokens, 10);
+        assert_eq!(usage.prompt_tokens_details.cached_tokens, 2);
+        assert_eq!(usage.completion_tokens_details.reasoning_tokens, 6);
+    }
+
+    #[test]
+    fn blocked_prompt_is_an_error() {
+        let response = response(json!({
+            "promptFeedback": {
+                "blockReason": "SAFETY",
+                "safetyRatings": [
+                    {"category": "HARM_CATEGORY_HARASSMENT", "probability": "HIGH"},
+                    {"category": "HARM_CATEGORY_HATE_SPEECH", "probability": "NEGLIGIBLE"}
+                ]
+            }
+        }));
+        let error = response.check_blocked().unwrap_err();
+
+        assert_eq!(error.exit_code(), 16);
+        assert_eq!(
+            error.to_string(),
+            "Content blocked: Gemini blocked the prompt (SAFETY) - flagged: HARM_CATEGORY_HARASSMENT (HIGH)"
+        );
+    }
+
+    #[test]
+    fn blocked_finish_reasons_are_errors() {
+        for reason in BLOCKED_FINISH_REASONS {
+            let response = response(json!({
+                "candidates": [{"content": {"parts": []}, "finishReason": reason}]
+            }));
+            assert!(
+                matches!(response.check_blocked(), Err(PgptError::ContentBlocked(message)) if message.contains(reason)),
+                "{} was not blocked",
+                reason
+            );
+        }
+        for reason in ["STOP", "MAX_TOKENS"] {
+            let response = response(json!({
+                "candidates": [{"content": {"parts": [{"text": "Hi"}]}, "finishReason": reason}]
+            }));
+            assert!(response.check_blocked().is_ok());
+        }
+    }
+
+    #[test]
+    fn stream_text_and_usage_are_read() {
+        let (response, tokens) = read_events(&[
+            json!({"candidates": [{"content": {"role": "model", "parts": [{"text": "Hel"}]}}]}),
+            json!({
+                "candidates": [{"content": {"role": "model", "parts": [{"text": "lo"}]}, "finishReason": "STOP"}],
+                "usageMetadata": {"promptTokenCount": 3, "candidatesTokenCount": 2, "totalTokenCount": 5},
+                "modelVersion": "gemini-2.0-flash-001"
+            }),
+        ]);
+        let response = response.unwrap();
+
+        assert_eq!(tokens, ["Hel", "lo"]);
+        assert_eq!(response.text, "Hello");
+        assert_eq!(response.model, "gemini-2.0-flash-001");
+        assert_eq!(response.finish_reason.as_deref(), Some("STOP"));
+        assert_eq!(response.usage.total_tokens, 5);
+    }
+
+    #[test]
+    fn stream_stops_when_blocked() {
+        let (response, tokens) = read_events(&[
+            json!({"candidates": [{"content": {"parts": [{"text": "Once"}]}}]}),
+            json!({"candidates": [{"content": {"parts": []}, "finishReason": "RECITATION"}]}),
+            json!({"candidates": [{"content": {"parts": [{"text": "never sent"}]}}]}),
+        ]);
+        let error = response.unwrap_err().downcast::<PgptError>().unwrap();
+
+        assert_eq!(tokens, ["Once"]);
+        assert!(matches!(error, PgptError::ContentBlocked(_)));
+    }
+}
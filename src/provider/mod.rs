@@ -0,0 +1,731 @@
+pub mod anthropic;
+pub mod gemini;
+pub mod ollama;
+pub mod retry;
+
+use crate::config::model::{Model, Provider};
+use crate::config::Config;
+use crate::error::PgptError;
+use crate::gpt::{
+    GPTClient, GPTImageUrl, GPTMessage, GPTRole, GPTTokenLogprob, GPTTool, GPTToolCall,
+    GPTToolChoice, GPTUsage,
+};
+use anthropic::AnthropicClient;
+use colored::*;
+use gemini::GeminiClient;
+use ollama::OllamaClient;
+use std::path::Path;
+
+/// Parameters that control how the model samples its response. Unset values use the provider defaults.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct SamplingParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+}
+
+/// The most stop sequences a request can have
+const MAX_STOP_SEQUENCES: usize = 4;
+
+/// Roughly how many characters make up a token, used to estimate the size of a prompt
+const CHARS_PER_TOKEN: u32 = 4;
+
+/// The tokens an image is assumed to take up (a high detail 1024x1024 image for OpenAI)
+const TOKENS_PER_IMAGE: u32 = 765;
+
+/// The tokens each message adds for its role and formatting
+const TOKENS_PER_MESSAGE: u32 = 4;
+
+impl SamplingParams {
+    /// Returns whether every parameter is unset
+    pub fn is_empty(&self) -> bool {
+        self.temperature.is_none()
+            && self.top_p.is_none()
+            && self.max_tokens.is_none()
+            && self.seed.is_none()
+            && self.stop.is_none()
+            && self.presence_penalty.is_none()
+            && self.frequency_penalty.is_none()
+    }
+
+    /// Returns these parameters with the ones set in `overrides` replaced
+    ///
+    /// ### Arguments
+    /// - `overrides` - The parameters that take precedence
+    pub fn with_overrides(&self, overrides: &SamplingParams) -> Self {
+        Self {
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            seed: overrides.seed.or(self.seed),
+            stop: overrides.stop.clone().or_else(|| self.stop.clone()),
+            presence_penalty: overrides.presence_penalty.or(self.presence_penalty),
+            frequency_penalty: overrides.frequency_penalty.or(self.frequency_penalty),
+        }
+    }
+
+    /// Checks that every set parameter is in the range the provider allows
+    ///
+    /// ### Arguments
+    /// - `provider` - The provider the parameters will be sent to
+    pub fn validate(&self, provider: Provider) -> Result<(), PgptError> {
+        let max_temperature = match provider {
+            Provider::Anthropic => 1.0,
+            _ => 2.0,
+        };
+
+        let mut errors: Vec<String> = Vec::new();
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=max_temperature).contains(&temperature) {
+                errors.push(format!(
+                    "temperature must be between 0 and {} for {} (got {})",
+                    max_temperature, provider, temperature
+                ));
+            }
+        }
+        if let Some(top_p) = self.top_p {
+            if !(0.0..=1.0).contains(&top_p) {
+                errors.push(format!("top-p must be between 0 and 1 (got {})", top_p));
+            }
+        }
+        if self.max_tokens == Some(0) {
+            errors.push(String::from("max-tokens must be at least 1"));
+        }
+        if let Some(stop) = &self.stop {
+            if stop.len() > MAX_STOP_SEQUENCES {
+                errors.push(format!(
+                    "at most {} stop sequences can be set (got {})",
+                    MAX_STOP_SEQUENCES,
+                    stop.len()
+                ));
+            }
+            if stop.iter().any(|sequence| sequence.is_empty()) {
+                errors.push(String::from("stop sequences cannot be empty"));
+            }
+        }
+        for (name, penalty) in [
+            ("presence-penalty", self.presence_penalty),
+            ("frequency-penalty", self.frequency_penalty),
+        ] {
+            if let Some(penalty) = penalty {
+                if !(-2.0..=2.0).contains(&penalty) {
+                    errors.push(format!(
+                        "{} must be between -2 and 2 (got {})",
+                        name, penalty
+                    ));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(PgptError::InvalidArgument(errors.join(", ")))
+        }
+    }
+
+    /// Returns the names of the set parameters that the provider ignores
+    ///
+    /// ### Arguments
+    /// - `provider` - The provider the parameters will be sent to
+    pub fn unsupported(&self, provider: Provider) -> Vec<&'static str> {
+        let mut unsupported = Vec::new();
+        if provider == Provider::Anthropic {
+            if self.seed.is_some() {
+                unsupported.push("seed");
+            }
+            if self.presence_penalty.is_some() {
+                unsupported.push("presence-penalty");
+            }
+            if self.frequency_penalty.is_some() {
+                unsupported.push("frequency-penalty");
+            }
+        }
+        unsupported
+    }
+
+    pub fn show(&self) {
+        let show = |name: &str, value: Option<String>| {
+            println!(
+                "{}: {}",
+                name.cyan(),
+                value.unwrap_or_else(|| String::from("Default"))
+            )
+        };
+        show("Temperature", self.temperature.map(|v| v.to_string()));
+        show("Top P", self.top_p.map(|v| v.to_string()));
+        show("Max Tokens", self.max_tokens.map(|v| v.to_string()));
+        show("Seed", self.seed.map(|v| v.to_string()));
+        show("Stop", self.stop.as_ref().map(|stop| format!("{:?}", stop)));
+        show(
+            "Presence Penalty",
+            self.presence_penalty.map(|v| v.to_string()),
+        );
+        show(
+            "Frequency Penalty",
+            self.frequency_penalty.map(|v| v.to_string()),
+        );
+    }
+}
+
+/// The format the response must be given in
+#[derive(Debug, Clone)]
+pub enum ResponseFormat {
+    /// Any JSON object
+    JsonObject,
+    /// JSON that matches a JSON Schema
+    JsonSchema {
+        /// The name of the schema (letters, digits, `_` and `-`)
+        name: String,
+        schema: serde_json::Value,
+    },
+}
+
+impl ResponseFormat {
+    /// Loads a JSON Schema file, checking that it is a valid schema
+    ///
+    /// ### Arguments
+    /// - `path` - The path of the schema file
+    pub fn from_schema_file(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read schema {:?}: {}", path, e))?;
+        let schema: serde_json::Value = serde_json::from_str(&contents).map_err(|e| {
+            PgptError::InvalidArgument(format!("{:?} is not valid JSON: {}", path, e))
+        })?;
+        jsonschema::validator_for(&schema).map_err(|e| {
+            PgptError::InvalidArgument(format!("{:?} is not a valid JSON Schema: {}", path, e))
+        })?;
+
+        let name: String = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .take(64)
+            .collect();
+        Ok(Self::JsonSchema {
+            name: if name.is_empty() {
+                String::from("response")
+            } else {
+                name
+            },
+            schema,
+        })
+    }
+
+    /// Checks that the response text is JSON in this format, returning the parsed JSON
+    ///
+    /// ### Arguments
+    /// - `text` - The text of the response
+    pub fn validate(&self, text: &str) -> Result<serde_json::Value, PgptError> {
+        let value: serde_json::Value = serde_json::from_str(text).map_err(|e| {
+            PgptError::SchemaValidation(format!("response is not valid JSON: {}", e))
+        })?;
+        match self {
+            Self::JsonObject => Ok(value),
+            Self::JsonSchema { schema, .. } => {
+                let validator = jsonschema::validator_for(schema)
+                    .map_err(|e| PgptError::InvalidArgument(e.to_string()))?;
+                let errors: Vec<String> = validator
+                    .iter_errors(&value)
+                    .map(|error| {
+                        let path = error.instance_path().to_string();
+                        if path.is_empty() {
+                            format!("- {}", error)
+                        } else {
+                            format!("- at {}: {}", path, error)
+                        }
+                    })
+                    .collect();
+                if errors.is_empty() {
+                    Ok(value)
+                } else {
+                    Err(PgptError::SchemaValidation(format!(
+                        "response does not match the schema\n{}",
+                        errors.join("\n")
+                    )))
+                }
+            }
+        }
+    }
+}
+
+/// A provider-neutral chat request
+#[derive(Debug, Clone)]
+pub struct ChatRequest {
+    pub model: Model,
+    pub messages: Vec<GPTMessage>,
+    pub params: SamplingParams,
+    /// The tools the model may call (only sent to providers that support tools)
+    pub tools: Vec<GPTTool>,
+    pub tool_choice: Option<GPTToolChoice>,
+    /// Forces the response to be JSON (only sent to providers that support it)
+    pub response_format: Option<ResponseFormat>,
+    /// How many choices to generate for the last message (only sent to providers that support it)
+    pub choices: u32,
+    /// Asks for token log probabilities with this many of the most likely tokens at each position
+    /// (only sent to providers that support it)
+    pub logprobs: Option<u32>,
+}
+
+impl ChatRequest {
+    pub fn builder() -> ChatRequestBuilder {
+        ChatRequestBuilder::new()
+    }
+
+    /// Roughly estimates how many tokens the messages will take up, assuming 4 characters per
+    /// token and a fixed cost for each image
+    pub fn estimated_prompt_tokens(&self) -> u32 {
+        self.messages
+            .iter()
+            .map(|message| {
+                let text = message.content.text().chars().count() as u32;
+                let images = message.content.images().len() as u32;
+                text / CHARS_PER_TOKEN + images * TOKENS_PER_IMAGE + TOKENS_PER_MESSAGE
+            })
+            .sum()
+    }
+}
+
+/// Builds a [`ChatRequest`] one setting at a time
+pub struct ChatRequestBuilder {
+    pub model: Option<Model>,
+    pub messages: Vec<GPTMessage>,
+    pub params: SamplingParams,
+    pub tools: Vec<GPTTool>,
+    pub tool_choice: Option<GPTToolChoice>,
+    pub response_format: Option<ResponseFormat>,
+    pub choices: u32,
+    pub logprobs: Option<u32>,
+}
+
+impl Default for ChatRequestBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChatRequestBuilder {
+    pub fn new() -> Self {
+        Self {
+            model: None,
+            messages: Vec::new(),
+            params: SamplingParams::default(),
+            tools: Vec::new(),
+            tool_choice: None,
+            response_format: None,
+            choices: 1,
+            logprobs: None,
+        }
+    }
+
+    /// Sets the model
+    ///
+    /// ### Arguments
+    /// - `model` - The model to use in the query
+    pub fn model(&mut self, model: &Model) -> &mut Self {
+        self.model = Some(model.clone());
+        self
+    }
+
+    /// Adds a message to the query
+    ///
+    /// ### Arguments
+    /// - `role` - The role of the message (e.g. "user")
+    /// - `content` - The content of the message
+    pub fn message(&mut self, role: GPTRole, content: &str) -> &mut Self {
+        self.messages.push(GPTMessage::new(role, content));
+        self
+    }
+
+    /// Adds a message with images attached to the query
+    ///
+    /// ### Arguments
+    /// - `role` - The role of the message (e.g. "user")
+    /// - `content` - The text of the message
+    /// - `images` - The images to attach
+    pub fn message_with_images(
+        &mut self,
+        role: GPTRole,
+        content: &str,
+        images: &[GPTImageUrl],
+    ) -> &mut Self {
+        self.messages
+            .push(GPTMessage::with_images(role, content, images));
+        self
+    }
+
+    /// Sets the sampling parameters
+    ///
+    /// ### Arguments
+    /// - `params` - The sampling parameters to use in the query
+    pub fn params(&mut self, params: &SamplingParams) -> &mut Self {
+        self.params = params.clone();
+        self
+    }
+
+    /// Sets the tools the model may call
+    ///
+    /// ### Arguments
+    /// - `tools` - The definitions of the tools
+    pub fn tools(&mut self, tools: &[GPTTool]) -> &mut Self {
+        self.tools = tools.to_vec();
+        self
+    }
+
+    /// Sets whether and which tools the model calls
+    ///
+    /// ### Arguments
+    /// - `tool_choice` - The tool choice to send with the tools
+    pub fn tool_choice(&mut self, tool_choice: GPTToolChoice) -> &mut Self {
+        self.tool_choice = Some(tool_choice);
+        self
+    }
+
+    /// Forces the response to be JSON
+    ///
+    /// ### Arguments
+    /// - `response_format` - The JSON format the response must be given in
+    pub fn response_format(&mut self, response_format: ResponseFormat) -> &mut Self {
+        self.response_format = Some(response_format);
+        self
+    }
+
+    /// Sets how many choices to generate
+    ///
+    /// ### Arguments
+    /// - `choices` - The number of choices
+    pub fn choices(&mut self, choices: u32) -> &mut Self {
+        self.choices = choices;
+        self
+    }
+
+    /// Asks for the log probability of each generated token
+    ///
+    /// ### Arguments
+    /// - `top_logprobs` - How many of the most likely tokens to return at each position (0-20)
+    pub fn logprobs(&mut self, top_logprobs: u32) -> &mut Self {
+        self.logprobs = Some(top_logprobs);
+        self
+    }
+
+    /// Builds the request
+    pub fn build(&self) -> anyhow::Result<ChatRequest> {
+        if self.model.is_none() {
+            return Err(anyhow::anyhow!("Cannot build request without model!"));
+        }
+
+        if self.messages.is_empty() {
+            return Err(anyhow::anyhow!("Cannot build request with no messages!"));
+        }
+
+        let request = ChatRequest {
+            model: self.model.clone().unwrap(),
+            messages: self.messages.clone(),
+            params: self.params.clone(),
+            tools: self.tools.clone(),
+            tool_choice: self.tool_choice.clone(),
+            response_format: self.response_format.clone(),
+            choices: self.choices,
+            logprobs: self.logprobs,
+        };
+        Ok(request)
+    }
+}
+
+/// A provider-neutral chat response
+#[derive(Debug)]
+pub struct ChatResponse {
+    /// The model that generated the response as reported by the provider
+    pub model: String,
+    pub text: String,
+    pub usage: GPTUsage,
+    pub finish_reason: Option<String>,
+    /// The tools the model asked to call before it can give a final answer
+    pub tool_calls: Vec<GPTToolCall>,
+    /// The text of the choices after the first, when several were requested
+    pub alternatives: Vec<String>,
+    /// The log probability of each token of the first choice, when they were requested
+    pub logprobs: Vec<GPTTokenLogprob>,
+}
+
+impl ChatResponse {
+    /// Returns the text of every choice in order
+    pub fn choices(&self) -> Vec<&str> {
+        std::iter::once(self.text.as_str())
+            .chain(self.alternatives.iter().map(String::as_str))
+            .collect()
+    }
+}
+
+/// A backend that can answer chat requests
+pub trait ChatProvider {
+    /// Whether the provider can generate several choices in one request
+    fn supports_choices(&self) -> bool {
+        false
+    }
+
+    /// Whether the provider can return the log probabilities of the generated tokens
+    fn supports_logprobs(&self) -> bool {
+        false
+    }
+
+    /// Lists the IDs of the models the provider offers
+    fn list_models(&self) -> anyhow::Result<Vec<String>>;
+
+    /// Sends the request and waits for the full response
+    ///
+    /// ### Arguments
+    /// - `request` - The request to send
+    fn chat(&self, request: &ChatRequest) -> anyhow::Result<ChatResponse>;
+
+    /// Sends the request, calling `on_token` with each piece of text as it arrives
+    ///
+    /// ### Arguments
+    /// - `request` - The request to send
+    /// - `on_token` - Called with each piece of the response text in order
+    fn chat_stream(
+        &self,
+        request: &ChatRequest,
+        on_token: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<ChatResponse>;
+}
+
+/// Creates the provider used to answer requests for the model
+///
+/// ### Arguments
+/// - `model` - The model the requests will be sent to
+/// - `config` - The loaded configuration
+/// - `base_url` - Overrides the API base URL of the provider (optional)
+pub fn create_provider(
+    model: &Model,
+    config: &Config,
+    base_url: Option<&str>,
+) -> anyhow::Result<Box<dyn ChatProvider>> {
+    match (model.provider(), &config.azure) {
+        (Provider::OpenAI | Provider::Azure, Some(azure)) if azure.is_enabled() => Ok(Box::new(
+            GPTClient::new_azure(
+                &config.api_key(Provider::Azure)?,
+                base_url.unwrap_or(&azure.endpoint),
+                azure.deployment(model)?,
+                &azure.api_version,
+            )?
+            .with_retry_policy(config.retry_policy.clone()),
+        )),
+        (provider, _) => create_client(provider, config, base_url),
+    }
+}
+
+/// Creates the client for a provider's own API
+///
+/// ### Arguments
+/// - `provider` - The provider to connect to
+/// - `config` - The loaded configuration
+/// - `base_url` - Overrides the API base URL of the provider (optional)
+pub fn create_client(
+    provider: Provider,
+    config: &Config,
+    base_url: Option<&str>,
+) -> anyhow::Result<Box<dyn ChatProvider>> {
+    let retry_policy = config.retry_policy.clone();
+    match provider {
+        Provider::OpenAI => Ok(Box::new(
+            GPTClient::new(
+                &config.api_key(Provider::OpenAI)?,
+                base_url.unwrap_or(&config.base_url),
+            )?
+            .with_retry_policy(retry_policy),
+        )),
+        Provider::Azure => Err(anyhow::anyhow!(
+            "Azure OpenAI is used through the deployments of OpenAI models, set them with `pgpt config set azure-deployment`"
+        )),
+        Provider::Anthropic => Ok(Box::new(
+            AnthropicClient::new(
+                &config.api_key(Provider::Anthropic)?,
+                base_url.unwrap_or(anthropic::DEFAULT_BASE_URL),
+            )?
+            .with_retry_policy(retry_policy),
+        )),
+        Provider::Gemini => Ok(Box::new(
+            GeminiClient::new(
+                &config.api_key(Provider::Gemini)?,
+                base_url.unwrap_or(gemini::DEFAULT_BASE_URL),
+            )?
+            .with_retry_policy(retry_policy),
+        )),
+        Provider::Ollama => {
+            let base_url = base_url
+                .map(String::from)
+                .unwrap_or_else(OllamaClient::base_url_from_env);
+            Ok(Box::new(
+                OllamaClient::new(&base_url)?.with_retry_policy(retry_policy),
+            ))
+        }
+    }
+}
+
+/// Extracts the payload of a server-sent event `data:` line, ignoring comments and other fields
+pub(crate) fn sse_data(line: &str) -> Option<&str> {
+    line.strip_prefix("data:").map(|data| data.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temperature(temperature: f32) -> SamplingParams {
+        SamplingParams {
+            temperature: Some(temperature),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn temperature_range_depends_on_the_provider() {
+        for provider in [Provider::OpenAI, Provider::Gemini, Provider::Ollama] {
+            assert!(temperature(0.0).validate(provider).is_ok());
+            assert!(temperature(2.0).validate(provider).is_ok());
+            assert!(temperature(2.1).validate(provider).is_err());
+            assert!(temperature(-0.1).validate(provider).is_err());
+        }
+        assert!(temperature(1.0).validate(Provider::Anthropic).is_ok());
+        assert!(temperature(1.1).validate(Provider::Anthropic).is_err());
+    }
+
+    #[test]
+    fn top_p_must_be_between_0_and_1() {
+        let top_p = |top_p| SamplingParams {
+            top_p: Some(top_p),
+            ..Default::default()
+        };
+
+        assert!(top_p(0.0).validate(Provider::OpenAI).is_ok());
+        assert!(top_p(1.0).validate(Provider::OpenAI).is_ok());
+        assert!(top_p(1.01).validate(Provider::OpenAI).is_err());
+        assert!(top_p(-0.01).validate(Provider::OpenAI).is_err());
+    }
+
+    #[test]
+    fn penalties_must_be_between_minus_2_and_2() {
+        for penalty in [-2.0, 0.0, 2.0] {
+            let params = SamplingParams {
+                presence_penalty: Some(penalty),
+                frequency_penalty: Some(penalty),
+                ..Default::default()
+            };
+            assert!(params.validate(Provider::OpenAI).is_ok());
+        }
+        for penalty in [-2.1, 2.1] {
+            let presence = SamplingParams {
+                presence_penalty: Some(penalty),
+                ..Default::default()
+            };
+            let frequency = SamplingParams {
+                frequency_penalty: Some(penalty),
+                ..Default::default()
+            };
+            assert!(presence.validate(Provider::OpenAI).is_err());
+            assert!(frequency.validate(Provider::OpenAI).is_err());
+        }
+    }
+
+    #[test]
+    fn max_tokens_and_stop_sequences_are_checked() {
+        let max_tokens = |max_tokens| SamplingParams {
+            max_tokens: Some(max_tokens),
+            ..Default::default()
+        };
+        let stop = |count: usize, sequence: &str| SamplingParams {
+            stop: Some(vec![sequence.to_string(); count]),
+            ..Default::default()
+        };
+
+        assert!(max_tokens(1).validate(Provider::OpenAI).is_ok());
+        assert!(max_tokens(0).validate(Provider::OpenAI).is_err());
+        assert!(stop(MAX_STOP_SEQUENCES, "END")
+            .validate(Provider::OpenAI)
+            .is_ok());
+        assert!(stop(MAX_STOP_SEQUENCES + 1, "END")
+            .validate(Provider::OpenAI)
+            .is_err());
+        assert!(stop(1, "").validate(Provider::OpenAI).is_err());
+    }
+
+    #[test]
+    fn every_invalid_parameter_is_reported() {
+        let params = SamplingParams {
+            temperature: Some(3.0),
+            top_p: Some(2.0),
+            ..Default::default()
+        };
+        let error = params.validate(Provider::OpenAI).unwrap_err();
+
+        assert_eq!(error.exit_code(), 2);
+        assert_eq!(
+            error.to_string(),
+            "Invalid argument: temperature must be between 0 and 2 for OpenAI (got 3), top-p must be between 0 and 1 (got 2)"
+        );
+    }
+
+    #[test]
+    fn cli_values_override_config_values() {
+        let config = SamplingParams {
+            temperature: Some(0.7),
+            seed: Some(1),
+            stop: Some(vec![String::from("END")]),
+            ..Default::default()
+        };
+        let cli = SamplingParams {
+            temperature: Some(0.5),
+            max_tokens: Some(10),
+            ..Default::default()
+        };
+        let params = config.with_overrides(&cli);
+
+        assert_eq!(params.temperature, Some(0.5));
+        assert_eq!(params.max_tokens, Some(10));
+        assert_eq!(params.seed, Some(1));
+        assert_eq!(params.stop, Some(vec![String::from("END")]));
+        assert_eq!(params.top_p, None);
+        assert!(SamplingParams::default()
+            .with_overrides(&SamplingParams::default())
+            .is_empty());
+    }
+
+    #[test]
+    fn anthropic_ignores_seed_and_penalties() {
+        let params = SamplingParams {
+            temperature: Some(0.5),
+            seed: Some(1),
+            presence_penalty: Some(0.5),
+            frequency_penalty: Some(0.5),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            params.unsupported(Provider::Anthropic),
+            ["seed", "presence-penalty", "frequency-penalty"]
+        );
+        for provider in [Provider::OpenAI, Provider::Gemini, Provider::Ollama] {
+            assert!(params.unsupported(provider).is_empty());
+        }
+    }
+}
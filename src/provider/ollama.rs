@@ -0,0 +1,472 @@
+use crate::error::{parse_json, PgptError};
+use crate::gpt::{GPTMessage, GPTRole, GPTUsage};
+use crate::provider::retry::{self, RetryPolicy};
+use crate::provider::{ChatProvider, ChatRequest, ChatResponse, ResponseFormat};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader};
+
+/// The address Ollama listens on by default
+pub const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+
+#[derive(Serialize, Debug)]
+pub struct OllamaQuery {
+    pub model: String,
+    pub messages: Vec<OllamaRequestMessage>,
+    pub stream: bool,
+    /// `"json"` or a JSON Schema the response must match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "OllamaOptions::is_empty")]
+    pub options: OllamaOptions,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OllamaRequestMessage {
+    pub role: GPTRole,
+    pub content: String,
+    /// Base64 encoded images (without the `data:` URL prefix)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+}
+
+impl From<&GPTMessage> for OllamaRequestMessage {
+    fn from(message: &GPTMessage) -> Self {
+        Self {
+            role: message.role.clone(),
+            content: message.content.text(),
+            // Ollama only accepts inline images, so images referenced by a web URL are skipped
+            images: message
+                .content
+                .images()
+                .iter()
+                .filter_map(|image| image.base64_data())
+                .map(|(_, data)| data.to_string())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+}
+
+impl OllamaOptions {
+    fn is_empty(&self) -> bool {
+        self.temperature.is_none()
+            && self.top_p.is_none()
+            && self.num_predict.is_none()
+            && self.seed.is_none()
+            && self.stop.is_none()
+            && self.presence_penalty.is_none()
+            && self.frequency_penalty.is_none()
+    }
+}
+
+/// A full response, or a single line of a streamed response
+#[derive(Deserialize, Debug)]
+pub struct OllamaResponse {
+    pub model: String,
+    pub message: Option<OllamaMessage>,
+    #[serde(default)]
+    pub done: bool,
+    pub done_reason: Option<String>,
+    #[serde(default)]
+    pub prompt_eval_count: i32,
+    #[serde(default)]
+    pub eval_count: i32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OllamaMessage {
+    pub content: String,
+}
+
+impl OllamaQuery {
+    /// Creates the Ollama query for a provider-neutral request
+    ///
+    /// ### Arguments
+    /// - `request` - The request to convert
+    pub fn from_request(request: &ChatRequest, stream: bool) -> Self {
+        Self {
+            model: request.model.api_model(),
+            messages: request
+                .messages
+                .iter()
+                .map(OllamaRequestMessage::from)
+                .collect(),
+            stream,
+            format: request.response_format.as_ref().map(|format| match format {
+                ResponseFormat::JsonObject => serde_json::Value::from("json"),
+                ResponseFormat::JsonSchema { schema, .. } => schema.clone(),
+            }),
+            options: OllamaOptions {
+                temperature: request.params.temperature,
+                top_p: request.params.top_p,
+                num_predict: request.params.max_tokens,
+                seed: request.params.seed,
+                stop: request.params.stop.clone(),
+                presence_penalty: request.params.presence_penalty,
+                frequency_penalty: request.params.frequency_penalty,
+            },
+        }
+    }
+}
+
+impl OllamaResponse {
+    fn usage(&self) -> GPTUsage {
+        GPTUsage {
+            prompt_tokens: self.prompt_eval_count,
+            completion_tokens: self.eval_count,
+            total_tokens: self.prompt_eval_count + self.eval_count,
+            ..Default::default()
+        }
+    }
+}
+
+/// The models pulled to an Ollama server
+#[derive(Deserialize, Debug)]
+pub struct OllamaModelList {
+    #[serde(default)]
+    pub models: Vec<OllamaModelInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OllamaModelInfo {
+    pub name: String,
+}
+
+pub struct OllamaClient {
+    http_client: Client,
+    chat_url: String,
+    tags_url: String,
+    retry_policy: RetryPolicy,
+}
+
+impl OllamaClient {
+    fn create_http_client() -> anyhow::Result<Client> {
+        let user_agent = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert(USER_AGENT, HeaderValue::from_str(&user_agent)?);
+        let client = Client::builder().default_headers(headers).build()?;
+        Ok(client)
+    }
+
+    /// Creates a client for the Ollama chat API
+    ///
+    /// ### Arguments
+    /// - `base_url` - The address of the Ollama server (e.g. `http://localhost:11434`)
+    pub fn new(base_url: &str) -> anyhow::Result<Self> {
+        let http_client = Self::create_http_client()?;
+        let base_url = base_url.trim_end_matches('/');
+        // `OLLAMA_HOST` is commonly set without a scheme (e.g. `127.0.0.1:11434`)
+        let base_url = if base_url.contains("://") {
+            base_url.to_string()
+        } else {
+            format!("http://{}", base_url)
+        };
+        Ok(Self {
+            http_client,
+            chat_url: format!("{}/api/chat", base_url),
+            tags_url: format!("{}/api/tags", base_url),
+            retry_policy: RetryPolicy::default(),
+        })
+    }
+
+    /// Sets how failed requests are retried
+    ///
+    /// ### Arguments
+    /// - `retry_policy` - The retry policy to use
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Gets the Ollama address from the `OLLAMA_HOST` env variable, otherwise the default
+    pub fn base_url_from_env() -> String {
+        match std::env::var("OLLAMA_HOST") {
+            Ok(host) if !host.trim().is_empty() => host.trim().to_string(),
+            _ => DEFAULT_BASE_URL.to_string(),
+        }
+    }
+
+    /// Sends the query, converting error responses into errors
+    fn send(&self, query: &OllamaQuery) -> anyhow::Result<Response> {
+        self.send_request(
+            self.http_client.post(&self.chat_url).json(query),
+            &self.chat_url,
+        )
+    }
+
+    /// Sends a request, explaining how to start Ollama when it cannot be reached
+    ///
+    /// ### Arguments
+    /// - `request` - The request to send
+    /// - `url` - The URL the request is sent to
+    fn send_request(&self, request: RequestBuilder, url: &str) -> anyhow::Result<Response> {
+        let response: Response = retry::send(request, &self.retry_policy).map_err(|e| {
+            match e.downcast::<PgptError>() {
+                Ok(PgptError::Network(message)) => PgptError::Network(format!(
+                    "Could not reach Ollama at {} (is `ollama serve` running?): {}",
+                    url, message
+                ))
+                .into(),
+                Ok(e) => e.into(),
+                Err(e) => e,
+            }
+        })?;
+
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            Err(PgptError::from_response(response).into())
+        }
+    }
+}
+
+impl ChatProvider for OllamaClient {
+    fn list_models(&self) -> anyhow::Result<Vec<String>> {
+        let response = self.send_request(self.http_client.get(&self.tags_url), &self.tags_url)?;
+        let list: OllamaModelList = parse_json(response)?;
+        Ok(list.models.into_iter().map(|model| model.name).collect())
+    }
+
+    fn chat(&self, request: &ChatRequest) -> anyhow::Result<ChatResponse> {
+        let query = OllamaQuery::from_request(request, false);
+        let response: OllamaResponse = parse_json(self.send(&query)?)?;
+        Ok(ChatResponse {
+            usage: response.usage(),
+            text: response
+                .message
+                .map(|message| message.content)
+                .unwrap_or_default(),
+            model: response.model,
+            finish_reason: response.done_reason,
+            tool_calls: Vec::new(),
+            alternatives: Vec::new(),
+            logprobs: Vec::new(),
+        })
+    }
+
+    fn chat_stream(
+        &self,
+        request: &ChatRequest,
+        on_token: &mut dyn FnMut(&str),
+    ) -> anyhow::Result<ChatResponse> {
+        let query = OllamaQuery::from_request(request, true);
+        let response = self.send(&query)?;
+        read_stream(BufReader::new(response), &query.model, on_token)
+    }
+}
+
+/// Reads a streamed response, calling `on_token` with each piece of text as it arrives
+///
+/// ### Arguments
+/// - `reader` - The newline-delimited JSON of the response
+/// - `model` - The model the request was sent to, until the stream reports it
+/// - `on_token` - Called with each piece of the response text in order
+fn read_stream(
+    reader: impl BufRead,
+    model: &str,
+    on_token: &mut dyn FnMut(&str),
+) -> anyhow::Result<ChatResponse> {
+    let mut chat_response = ChatResponse {
+        model: model.to_string(),
+        text: String::new(),
+        usage: GPTUsage::default(),
+        finish_reason: None,
+        tool_calls: Vec::new(),
+        alternatives: Vec::new(),
+        logprobs: Vec::new(),
+    };
+    // Streamed responses are newline-delimited JSON objects
+    for line in reader.lines() {
+        let line = line.map_err(PgptError::from)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let value: serde_json::Value = serde_json::from_str(&line)
+            .map_err(|e| PgptError::MalformedResponse(format!("{}: {}", e, line)))?;
+        if value.get("error").is_some() {
+            return Err(PgptError::from_error_body(&line).into());
+        }
+        let chunk: OllamaResponse = serde_json::from_value(value)
+            .map_err(|e| PgptError::MalformedResponse(format!("{}: {}", e, line)))?;
+        if let Some(message) = &chunk.message {
+            if !message.content.is_empty() {
+                chat_response.text.push_str(&message.content);
+                on_token(&message.content);
+            }
+        }
+        if chunk.done {
+            chat_response.usage = chunk.usage();
+            chat_response.model = chunk.model;
+            chat_response.finish_reason = chunk.done_reason;
+            break;
+        }
+    }
+    Ok(chat_response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::model::Model;
+    use crate::gpt::GPTImageUrl;
+    use crate::provider::SamplingParams;
+    use serde_json::json;
+
+    /// Reads a stream made of the given lines, returning the response and the tokens passed to
+    /// `on_token`
+    fn read_lines(lines: &[&str]) -> (anyhow::Result<ChatResponse>, Vec<String>) {
+        let stream = lines.join("\n");
+        let mut tokens = Vec::new();
+        let response = read_stream(stream.as_bytes(), "llama-test", &mut |token| {
+            tokens.push(token.to_string())
+        });
+        (response, tokens)
+    }
+
+    #[test]
+    fn query_keeps_messages_and_sets_options() {
+        let mut request = ChatRequest::builder()
+            .model(&Model::Ollama(String::from("llama3.2")))
+            .params(&SamplingParams {
+                temperature: Some(0.5),
+                max_tokens: Some(50),
+                ..Default::default()
+            })
+            .message(GPTRole::System, "Be brief.")
+            .message(GPTRole::User, "Hi")
+            .build()
+            .unwrap();
+        request.response_format = Some(ResponseFormat::JsonObject);
+        let query = serde_json::to_value(OllamaQuery::from_request(&request, true)).unwrap();
+
+        assert_eq!(
+            query,
+            json!({
+                "model": "llama3.2",
+                "messages": [
+                    {"role": "system", "content": "Be brief."},
+                    {"role": "user", "content": "Hi"}
+                ],
+                "stream": true,
+                "format": "json",
+                "options": {"temperature": 0.5, "num_predict": 50}
+            })
+        );
+    }
+
+    #[test]
+    fn base64_images_are_sent_without_the_data_url_prefix() {
+        let images = [
+            GPTImageUrl {
+                url: String::from("data:image/png;base64,aGVsbG8="),
+                detail: None,
+            },
+            // Web URLs cannot be sent to Ollama
+            GPTImageUrl {
+                url: String::from("https://example.com/cat.png"),
+                detail: None,
+            },
+        ];
+        let request = ChatRequest::builder()
+            .model(&Model::Ollama(String::from("llava")))
+            .message_with_images(GPTRole::User, "What is this?", &images)
+            .build()
+            .unwrap();
+        let query = serde_json::to_value(OllamaQuery::from_request(&request, false)).unwrap();
+
+        assert_eq!(
+            query["messages"],
+            json!([{"role": "user", "content": "What is this?", "images": ["aGVsbG8="]}])
+        );
+        assert_eq!(query["stream"], json!(false));
+    }
+
+    #[test]
+    fn response_usage_is_read() {
+        let response: OllamaResponse = serde_json::from_value(json!({
+            "model": "llama3.2",
+            "message": {"role": "assistant", "content": "Hello"},
+            "done": true,
+            "done_reason": "stop",
+            "prompt_eval_count": 7,
+            "eval_count": 9
+        }))
+        .unwrap();
+        let usage = response.usage();
+
+        assert_eq!(usage.prompt_tokens, 7);
+        assert_eq!(usage.completion_tokens, 9);
+        assert_eq!(usage.total_tokens, 16);
+    }
+
+    #[test]
+    fn stream_chunks_and_usage_are_read() {
+        let (response, tokens) = read_lines(&[
+            r#"{"model":"llama3.2","message":{"role":"assistant","content":"Hel"},"done":false}"#,
+            "",
+            r#"{"model":"llama3.2","message":{"role":"assistant","content":"lo"},"done":false}"#,
+            r#"{"model":"llama3.2","message":{"role":"assistant","content":""},"done":true,"done_reason":"stop","prompt_eval_count":7,"eval_count":9}"#,
+            r#"{"model":"llama3.2","message":{"role":"assistant","content":"ignored"},"done":false}"#,
+        ]);
+        let response = response.unwrap();
+
+        assert_eq!(tokens, ["Hel", "lo"]);
+        assert_eq!(response.text, "Hello");
+        assert_eq!(response.model, "llama3.2");
+        assert_eq!(response.finish_reason.as_deref(), Some("stop"));
+        assert_eq!(response.usage.prompt_tokens, 7);
+        assert_eq!(response.usage.completion_tokens, 9);
+        assert_eq!(response.usage.total_tokens, 16);
+    }
+
+    #[test]
+    fn stream_error_line_is_classified() {
+        let (response, tokens) = read_lines(&[
+            r#"{"model":"llama3.2","message":{"role":"assistant","content":"Hi"},"done":false}"#,
+            r#"{"error":"model runner has unexpectedly stopped"}"#,
+        ]);
+        let error = response.unwrap_err().downcast::<PgptError>().unwrap();
+
+        assert_eq!(tokens, ["Hi"]);
+        assert!(matches!(error, PgptError::Api { status: None, .. }));
+        assert_eq!(
+            error.to_string(),
+            "API error: model runner has unexpectedly stopped"
+        );
+
+        let (response, _) =
+            read_lines(&[r#"{"error":"prompt is too long for the context length"}"#]);
+        let error = response.unwrap_err().downcast::<PgptError>().unwrap();
+        assert!(matches!(error, PgptError::ContextLengthExceeded(_)));
+    }
+
+    #[test]
+    fn malformed_stream_line_fails() {
+        let (response, tokens) = read_lines(&["not json"]);
+        let error = response.unwrap_err().downcast::<PgptError>().unwrap();
+
+        assert!(matches!(error, PgptError::MalformedResponse(_)));
+        assert!(tokens.is_empty());
+    }
+}
@@ -0,0 +1,235 @@
+use crate::error::PgptError;
+use colored::*;
+use rand::Rng;
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use std::time::{Duration, SystemTime};
+
+/// The wait before the first retry, doubled for each retry after
+const BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// How failed requests are retried
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The number of times a request is retried before giving up
+    pub max_retries: u32,
+    /// The longest pgpt will wait before a retry
+    pub max_wait: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            max_wait: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the jittered exponential backoff for the retry
+    ///
+    /// ### Arguments
+    /// - `attempt` - The number of attempts made so far (starting at 1)
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = BASE_DELAY.saturating_mul(2u32.saturating_pow(attempt - 1));
+        let capped = exponential.min(self.max_wait);
+        capped.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+/// Sends the request, retrying rate limit (429), server (5xx) and transient network errors.
+/// Other responses, including client errors like 400, are returned as they are.
+///
+/// ### Arguments
+/// - `request` - The request to send (its body must be cloneable, e.g. JSON)
+/// - `policy` - How to retry failed requests
+pub fn send(request: RequestBuilder, policy: &RetryPolicy) -> anyhow::Result<Response> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let attempt_request = request
+            .try_clone()
+            .ok_or_else(|| anyhow::anyhow!("Request cannot be retried"))?;
+        let can_retry = attempt <= policy.max_retries;
+
+        let (reason, wait) = match attempt_request.send() {
+            Ok(response) if can_retry && is_retryable_status(response.status()) => {
+                let wait = server_wait(response.headers());
+                match wait {
+                    // Waiting less than the server asked for would only fail again
+                    Some(wait) if wait > policy.max_wait => return Ok(response),
+                    _ => (
+                        response.status().to_string(),
+                        wait.unwrap_or_else(|| policy.backoff(attempt)),
+                    ),
+                }
+            }
+            Ok(response) => return Ok(response),
+            Err(e) if can_retry && (e.is_timeout() || e.is_connect()) => {
+                (e.to_string(), policy.backoff(attempt))
+            }
+            Err(e) => return Err(PgptError::from(e).into()),
+        };
+
+        eprintln!(
+            "{}",
+            format!(
+                "Request failed ({}), retrying in {:.1}s ({}/{})",
+                reason,
+                wait.as_secs_f64(),
+                attempt,
+                policy.max_retries
+            )
+            .yellow()
+        );
+        std::thread::sleep(wait);
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Gets how long the server asked to wait from the `retry-after-ms`, `Retry-After` or
+/// `x-ratelimit-reset-*` headers
+fn server_wait(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+    if let Some(millis) = header("retry-after-ms").and_then(|ms| ms.trim().parse::<f64>().ok()) {
+        return duration_from_secs(millis / 1000.0);
+    }
+    if let Some(retry_after) = header("retry-after").map(str::trim) {
+        if let Ok(secs) = retry_after.parse::<f64>() {
+            return duration_from_secs(secs);
+        }
+        // Or the time to retry at, e.g. `Wed, 21 Oct 2015 07:28:00 GMT`
+        if let Ok(time) = httpdate::parse_http_date(retry_after) {
+            return Some(time.duration_since(SystemTime::now()).unwrap_or_default());
+        }
+    }
+    // Waiting for the later reset covers whichever limit was hit
+    ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"]
+        .iter()
+        .filter_map(|name| header(name).and_then(parse_reset_duration))
+        .max()
+}
+
+/// Parses a rate limit reset duration such as `20ms`, `1s`, `6m0s` or `1h2m3.5s`
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut number = String::new();
+    let mut chars = value.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+        let amount: f64 = number.parse().ok()?;
+        number.clear();
+        total += match c {
+            'h' => amount * 3600.0,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                amount / 1000.0
+            }
+            'm' => amount * 60.0,
+            's' => amount,
+            _ => return None,
+        };
+    }
+    // A bare number is in seconds
+    if !number.is_empty() {
+        total += number.parse::<f64>().ok()?;
+    }
+    duration_from_secs(total)
+}
+
+/// Converts the seconds given by a header into a wait. Negative waits are no wait, and waits too
+/// long to represent are [`Duration::MAX`], which is always longer than the policy allows.
+fn duration_from_secs(secs: f64) -> Option<Duration> {
+    if secs.is_nan() {
+        return None;
+    }
+    Some(Duration::try_from_secs_f64(secs.max(0.0)).unwrap_or(Duration::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    /// The wait asked for by a single header
+    fn wait_for(name: &'static str, value: &str) -> Option<Duration> {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        server_wait(&headers)
+    }
+
+    #[test]
+    fn reset_durations_are_parsed() {
+        assert_eq!(
+            parse_reset_duration("20ms"),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(parse_reset_duration("1s"), Some(Duration::from_secs(1)));
+        assert_eq!(parse_reset_duration("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(
+            parse_reset_duration("1h2m3.5s"),
+            Some(Duration::from_secs_f64(3723.5))
+        );
+        assert_eq!(
+            parse_reset_duration("2.5"),
+            Some(Duration::from_millis(2500))
+        );
+    }
+
+    #[test]
+    fn garbage_is_ignored() {
+        assert_eq!(parse_reset_duration("soon"), None);
+        assert_eq!(parse_reset_duration("5d"), None);
+        assert_eq!(wait_for("retry-after", "soon"), None);
+        assert_eq!(wait_for("retry-after-ms", "NaN"), None);
+        assert_eq!(wait_for("x-ratelimit-reset-tokens", "1x"), None);
+    }
+
+    #[test]
+    fn retry_after_is_read_in_seconds_or_milliseconds() {
+        assert_eq!(wait_for("retry-after", "2"), Some(Duration::from_secs(2)));
+        assert_eq!(wait_for("retry-after", "-3"), Some(Duration::ZERO));
+        assert_eq!(
+            wait_for("retry-after-ms", "1500"),
+            Some(Duration::from_millis(1500))
+        );
+    }
+
+    #[test]
+    fn retry_after_is_read_as_an_http_date() {
+        let later = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+        let wait = wait_for("retry-after", &later).unwrap();
+        assert!(wait > Duration::from_secs(55) && wait <= Duration::from_secs(60));
+
+        let earlier = httpdate::fmt_http_date(SystemTime::now() - Duration::from_secs(60));
+        assert_eq!(wait_for("retry-after", &earlier), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn huge_waits_do_not_overflow() {
+        assert_eq!(
+            wait_for("retry-after", "99999999999999999999"),
+            Some(Duration::MAX)
+        );
+        assert_eq!(wait_for("retry-after-ms", "inf"), Some(Duration::MAX));
+        let huge = format!("{}s", "9".repeat(400));
+        assert_eq!(parse_reset_duration(&huge), Some(Duration::MAX));
+    }
+
+    #[test]
+    fn later_reset_is_used() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-reset-requests", HeaderValue::from_static("1s"));
+        headers.insert("x-ratelimit-reset-tokens", HeaderValue::from_static("6m0s"));
+        assert_eq!(server_wait(&headers), Some(Duration::from_secs(360)));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::gpt::GPTTokenLogprob;
+use colored::*;
+use std::io::Write;
+use termimad::MadSkin;
+
+/// How token log probabilities are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogprobsDisplay {
+    /// The response text with each token colored by its probability
+    Color,
+    /// A table with the probability of each token and the most likely alternatives
+    Table,
+}
+
+/// Renders markdown that arrives in pieces (e.g. a streamed response).
+///
+/// Complete lines are rendered as soon as they arrive. Fenced code blocks and tables are held back
+/// until the whole block has arrived since their formatting depends on every line in the block.
+pub struct MarkdownStream<'a> {
+    skin: &'a MadSkin,
+    partial: String,
+    pending: String,
+    in_code_block: bool,
+}
+
+impl<'a> MarkdownStream<'a> {
+    pub fn new(skin: &'a MadSkin) -> Self {
+        Self {
+            skin,
+            partial: String::new(),
+            pending: String::new(),
+            in_code_block: false,
+        }
+    }
+
+    /// Adds a piece of markdown, rendering any lines that are complete
+    ///
+    /// ### Arguments
+    /// - `token` - The markdown text to add
+    pub fn push(&mut self, token: &str) {
+        self.partial.push_str(token);
+        while let Some(idx) = self.partial.find('\n') {
+            let line: String = self.partial.drain(..=idx).collect();
+            self.push_line(&line);
+        }
+    }
+
+    /// Renders whatever is left once no more text will arrive
+    pub fn finish(&mut self) {
+        let rest = std::mem::take(&mut self.partial);
+        self.pending.push_str(&rest);
+        self.flush();
+    }
+
+    fn push_line(&mut self, line: &str) {
+        let trimmed = line.trim_start();
+        let is_fence = trimmed.starts_with("```") || trimmed.starts_with("~~~");
+        let in_table = trimmed.starts_with('|');
+
+        // A table ends on the first line that isn't part of it
+        if !self.in_code_block && !in_table && self.pending.trim_start().starts_with('|') {
+            self.flush();
+        }
+
+        self.pending.push_str(line);
+        if is_fence {
+            self.in_code_block = !self.in_code_block;
+            if !self.in_code_block {
+                self.flush();
+            }
+        } else if !self.in_code_block && !in_table {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let text = std::mem::take(&mut self.pending);
+        print!("{}", self.skin.term_text(&text));
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Prints the log probabilities of the tokens of a response
+///
+/// ### Arguments
+/// - `tokens` - The tokens of the response with their log probabilities
+/// - `display` - How to show them
+pub fn print_logprobs(tokens: &[GPTTokenLogprob], display: LogprobsDisplay) {
+    println!("{}", "Token probabilities".cyan());
+    if tokens.is_empty() {
+        println!("{}", "The provider did not return any".yellow());
+        return;
+    }
+
+    match display {
+        LogprobsDisplay::Color => {
+            let text: String = tokens
+                .iter()
+                .map(|token| color_by_probability(&token.token, token.probability()).to_string())
+                .collect();
+            println!("{}", text);
+            println!("{} {} {}", "≥ 90%".green(), "≥ 50%".yellow(), "< 50%".red());
+        }
+        LogprobsDisplay::Table => {
+            let width = tokens
+                .iter()
+                .map(|token| format!("{:?}", token.token).chars().count())
+                .max()
+                .unwrap_or(0)
+                .max("Token".len());
+            println!(
+                "{:<width$}  {:>11}  Top alternatives",
+                "Token", "Probability"
+            );
+            for token in tokens.iter() {
+                let alternatives: Vec<String> = token
+                    .top_logprobs
+                    .iter()
+                    .map(|top| format!("{:?} {:.2}%", top.token, top.logprob.exp() * 100.0))
+                    .collect();
+                let probability = format!("{:.2}%", token.probability() * 100.0);
+                println!(
+                    "{:<width$}  {}  {}",
+                    format!("{:?}", token.token),
+                    color_by_probability(&format!("{:>11}", probability), token.probability()),
+                    alternatives.join(", ")
+                );
+            }
+        }
+    }
+}
+
+/// Colors text green, yellow or red depending on how likely it was
+fn color_by_probability(text: &str, probability: f64) -> ColoredString {
+    if probability >= 0.9 {
+        text.green()
+    } else if probability >= 0.5 {
+        text.yellow()
+    } else {
+        text.red()
+    }
+}
@@ -0,0 +1,192 @@
+use crate::gpt::{GPTTool, GPTToolCall};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The largest file `read_file` returns to the model
+const MAX_READ_BYTES: u64 = 100 * 1024;
+
+/// Runs a tool with the parsed arguments, returning the text sent back to the model
+pub type ToolHandler = Box<dyn Fn(&Value) -> anyhow::Result<String>>;
+
+/// The tools the model may call and the Rust functions that handle them
+#[derive(Default)]
+pub struct ToolRegistry {
+    tools: Vec<(GPTTool, ToolHandler)>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry with the built-in tools (`current_time`, `read_file` and `list_directory`)
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(
+            GPTTool::function(
+                "current_time",
+                "Gets the current date and time in UTC",
+                json!({ "type": "object", "properties": {} }),
+            ),
+            |_| Ok(current_time()),
+        );
+        registry.register(
+            GPTTool::function(
+                "read_file",
+                "Reads a text file from the user's working directory",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "The path of the file" }
+                    },
+                    "required": ["path"]
+                }),
+            ),
+            |args| read_file(string_arg(args, "path")?),
+        );
+        registry.register(
+            GPTTool::function(
+                "list_directory",
+                "Lists the entries of a directory in the user's working directory",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "The path of the directory" }
+                    },
+                    "required": ["path"]
+                }),
+            ),
+            |args| list_directory(string_arg(args, "path")?),
+        );
+        registry
+    }
+
+    /// Registers a tool, replacing any tool with the same name
+    ///
+    /// ### Arguments
+    /// - `tool` - The definition sent to the model
+    /// - `handler` - Called with the arguments when the model calls the tool
+    pub fn register(
+        &mut self,
+        tool: GPTTool,
+        handler: impl Fn(&Value) -> anyhow::Result<String> + 'static,
+    ) -> &mut Self {
+        self.tools
+            .retain(|(existing, _)| existing.function.name != tool.function.name);
+        self.tools.push((tool, Box::new(handler)));
+        self
+    }
+
+    /// The definitions of the registered tools
+    pub fn definitions(&self) -> Vec<GPTTool> {
+        self.tools.iter().map(|(tool, _)| tool.clone()).collect()
+    }
+
+    /// Runs the handler for a tool call. Failures are returned as text so the model can see what
+    /// went wrong and recover.
+    ///
+    /// ### Arguments
+    /// - `tool_call` - The call the model made
+    pub fn call(&self, tool_call: &GPTToolCall) -> String {
+        let name = &tool_call.function.name;
+        let handler = match self
+            .tools
+            .iter()
+            .find(|(tool, _)| &tool.function.name == name)
+        {
+            Some((_, handler)) => handler,
+            None => return format!("Error: there is no tool named `{}`", name),
+        };
+
+        let arguments = if tool_call.function.arguments.trim().is_empty() {
+            Ok(json!({}))
+        } else {
+            serde_json::from_str(&tool_call.function.arguments)
+        };
+        match arguments {
+            Ok(arguments) => handler(&arguments).unwrap_or_else(|e| format!("Error: {:#}", e)),
+            Err(e) => format!("Error: the arguments are not valid JSON: {}", e),
+        }
+    }
+}
+
+/// Gets a required string argument
+fn string_arg<'a>(args: &'a Value, name: &str) -> anyhow::Result<&'a str> {
+    args.get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow::anyhow!("Missing string argument `{}`", name))
+}
+
+/// Formats the current time as an RFC 3339 UTC timestamp
+fn current_time() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Converts days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+/// Checks that a path the model asked for stays inside the working directory
+fn working_dir_path(path: &str) -> anyhow::Result<std::path::PathBuf> {
+    let cwd = std::env::current_dir()?.canonicalize()?;
+    let resolved = cwd
+        .join(Path::new(path))
+        .canonicalize()
+        .map_err(|e| anyhow::anyhow!("{}: {}", path, e))?;
+    if !resolved.starts_with(&cwd) {
+        anyhow::bail!("{} is outside the working directory", path);
+    }
+    Ok(resolved)
+}
+
+fn read_file(path: &str) -> anyhow::Result<String> {
+    let resolved = working_dir_path(path)?;
+    let size = std::fs::metadata(&resolved)?.len();
+    if size > MAX_READ_BYTES {
+        anyhow::bail!(
+            "{} is {} bytes, larger than the {} byte limit",
+            path,
+            size,
+            MAX_READ_BYTES
+        );
+    }
+    std::fs::read_to_string(&resolved).map_err(|e| anyhow::anyhow!("{}: {}", path, e))
+}
+
+fn list_directory(path: &str) -> anyhow::Result<String> {
+    let resolved = working_dir_path(path)?;
+    let mut entries: Vec<String> = std::fs::read_dir(&resolved)?
+        .filter_map(Result::ok)
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => format!("{}/", name),
+                _ => name,
+            }
+        })
+        .collect();
+    entries.sort();
+    Ok(entries.join("\n"))
+}